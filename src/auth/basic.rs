@@ -0,0 +1,46 @@
+use anyhow::Result;
+use base64::{engine::general_purpose::STANDARD, Engine};
+use reqwest::header::{HeaderValue, AUTHORIZATION};
+use reqwest::Request;
+
+use super::Auth;
+
+/// `Authorization: Basic base64(user:pass)`.
+pub struct Basic {
+    header: HeaderValue,
+}
+
+impl Basic {
+    pub fn new(user: &str, password: &str) -> Result<Self> {
+        let encoded = STANDARD.encode(format!("{}:{}", user, password));
+        let mut header: HeaderValue = format!("Basic {}", encoded).parse()?;
+        header.set_sensitive(true);
+        Ok(Self { header })
+    }
+}
+
+impl Auth for Basic {
+    fn apply(&self, req: &mut Request) -> Result<()> {
+        req.headers_mut().insert(AUTHORIZATION, self.header.clone());
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use reqwest::Method;
+
+    #[test]
+    fn test_basic() {
+        let mut req = Request::new(Method::GET, "http://localhost/".parse().unwrap());
+        Basic::new("Aladdin", "open sesame")
+            .unwrap()
+            .apply(&mut req)
+            .unwrap();
+        assert_eq!(
+            req.headers()[AUTHORIZATION],
+            "Basic QWxhZGRpbjpvcGVuIHNlc2FtZQ=="
+        );
+    }
+}
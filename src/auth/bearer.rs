@@ -0,0 +1,25 @@
+use anyhow::Result;
+use reqwest::header::{HeaderValue, AUTHORIZATION};
+use reqwest::Request;
+
+use super::Auth;
+
+/// `Authorization: Bearer token`.
+pub struct Bearer {
+    header: HeaderValue,
+}
+
+impl Bearer {
+    pub fn new(token: &str) -> Result<Self> {
+        let mut header: HeaderValue = format!("Bearer {}", token).parse()?;
+        header.set_sensitive(true);
+        Ok(Self { header })
+    }
+}
+
+impl Auth for Bearer {
+    fn apply(&self, req: &mut Request) -> Result<()> {
+        req.headers_mut().insert(AUTHORIZATION, self.header.clone());
+        Ok(())
+    }
+}
@@ -0,0 +1,320 @@
+use anyhow::{anyhow, Result};
+use md5::Md5;
+use reqwest::header::{HeaderValue, AUTHORIZATION, WWW_AUTHENTICATE};
+use reqwest::{Request, Response};
+use sha2::{Digest as _, Sha256};
+
+use super::Auth;
+use crate::util;
+
+/// RFC 7616 digest access authentication, sent in answer to the server's 401 challenge.
+pub struct Digest {
+    user: String,
+    password: String,
+}
+
+/// the parameters of a `WWW-Authenticate: Digest ...` challenge.
+#[derive(Debug, PartialEq)]
+struct Challenge {
+    realm: String,
+    nonce: String,
+    opaque: Option<String>,
+    algorithm: String,
+    qop: Option<String>,
+}
+
+impl Challenge {
+    fn parse(header: &str) -> Option<Self> {
+        let (scheme, params) = header.trim().split_once(' ')?;
+        if !scheme.eq_ignore_ascii_case("digest") {
+            return None;
+        }
+        let params = parse_params(params);
+        let get = |key: &str| {
+            params
+                .iter()
+                .find(|(k, _)| k == key)
+                .map(|(_, v)| v.clone())
+        };
+        Some(Self {
+            realm: get("realm").unwrap_or_default(),
+            nonce: get("nonce")?,
+            opaque: get("opaque"),
+            algorithm: get("algorithm").unwrap_or_else(|| "MD5".into()),
+            qop: get("qop"),
+        })
+    }
+
+    /// `auth` when offered, `auth-int` only when the body can be hashed.
+    fn qop(&self, has_body: bool) -> Option<&'static str> {
+        let offered: Vec<&str> = self.qop.as_deref()?.split(',').map(str::trim).collect();
+        if offered.contains(&"auth") {
+            Some("auth")
+        } else if offered.contains(&"auth-int") && has_body {
+            Some("auth-int")
+        } else {
+            None
+        }
+    }
+
+    fn sess(&self) -> bool {
+        self.algorithm.to_ascii_uppercase().ends_with("-SESS")
+    }
+
+    fn hash(&self, data: &[u8]) -> Result<String> {
+        let algorithm = self.algorithm.to_ascii_uppercase();
+        match algorithm.trim_end_matches("-SESS") {
+            "MD5" => Ok(format!("{:x}", Md5::digest(data))),
+            "SHA-256" => Ok(format!("{:x}", Sha256::digest(data))),
+            _ => Err(anyhow!("Unsupported digest algorithm {}", self.algorithm)),
+        }
+    }
+}
+
+/// comma separated `key=value` and `key="quoted value"` pairs, keys lowercased.
+fn parse_params(s: &str) -> Vec<(String, String)> {
+    let mut params = Vec::new();
+    let mut rest = s;
+    loop {
+        rest = rest.trim_start_matches(|c: char| c == ',' || c.is_whitespace());
+        let (key, after) = match rest.split_once('=') {
+            Some(pair) => pair,
+            None => return params,
+        };
+        let after = after.trim_start();
+        let (value, next) = match after.strip_prefix('"') {
+            Some(quoted) => {
+                let mut value = String::new();
+                let mut end = quoted.len();
+                let mut chars = quoted.char_indices();
+                while let Some((i, c)) = chars.next() {
+                    match c {
+                        '\\' => value.extend(chars.next().map(|(_, c)| c)),
+                        '"' => {
+                            end = i + 1;
+                            break;
+                        }
+                        c => value.push(c),
+                    }
+                }
+                (value, &quoted[end..])
+            }
+            None => {
+                let end = after.find(',').unwrap_or(after.len());
+                (after[..end].trim().to_string(), &after[end..])
+            }
+        };
+        params.push((key.trim().to_ascii_lowercase(), value));
+        rest = next;
+    }
+}
+
+fn quote(s: &str) -> String {
+    format!("\"{}\"", s.replace('\\', "\\\\").replace('"', "\\\""))
+}
+
+fn cnonce() -> String {
+    format!("{:016x}", util::random())
+}
+
+impl Digest {
+    pub fn new(user: String, password: String) -> Self {
+        Self { user, password }
+    }
+
+    /// the `response` parameter, see RFC 7616 section 3.4.1.
+    #[allow(clippy::too_many_arguments)]
+    fn response(
+        &self,
+        c: &Challenge,
+        method: &str,
+        uri: &str,
+        qop: Option<&str>,
+        nc: &str,
+        cnonce: &str,
+        body: &[u8],
+    ) -> Result<String> {
+        let h = |data: String| c.hash(data.as_bytes());
+        let mut ha1 = h(format!("{}:{}:{}", self.user, c.realm, self.password))?;
+        if c.sess() {
+            ha1 = h(format!("{}:{}:{}", ha1, c.nonce, cnonce))?;
+        }
+        let ha2 = match qop {
+            Some("auth-int") => h(format!("{}:{}:{}", method, uri, c.hash(body)?))?,
+            _ => h(format!("{}:{}", method, uri))?,
+        };
+        match qop {
+            Some(qop) => h(format!(
+                "{}:{}:{}:{}:{}:{}",
+                ha1, c.nonce, nc, cnonce, qop, ha2
+            )),
+            None => h(format!("{}:{}:{}", ha1, c.nonce, ha2)),
+        }
+    }
+}
+
+impl Auth for Digest {
+    /// nothing to send until the server has sent a nonce.
+    fn apply(&self, _req: &mut Request) -> Result<()> {
+        Ok(())
+    }
+
+    fn respond(&self, req: &mut Request, resp: &Response) -> Result<bool> {
+        let challenge = resp
+            .headers()
+            .get_all(WWW_AUTHENTICATE)
+            .iter()
+            .filter_map(|v| v.to_str().ok())
+            .find_map(Challenge::parse);
+        let challenge = match challenge {
+            Some(challenge) => challenge,
+            None => return Ok(false),
+        };
+
+        let uri = match req.url().query() {
+            Some(query) => format!("{}?{}", req.url().path(), query),
+            None => req.url().path().to_string(),
+        };
+        let body = req.body().map(|b| b.as_bytes());
+        let qop = challenge.qop(!matches!(body, Some(None)));
+        let body = body.flatten().unwrap_or_default();
+        let (nc, cnonce) = ("00000001", cnonce());
+        let response = self.response(
+            &challenge,
+            req.method().as_str(),
+            &uri,
+            qop,
+            nc,
+            &cnonce,
+            body,
+        )?;
+
+        let mut header = format!(
+            "Digest username={}, realm={}, nonce={}, uri={}, algorithm={}, response=\"{}\"",
+            quote(&self.user),
+            quote(&challenge.realm),
+            quote(&challenge.nonce),
+            quote(&uri),
+            challenge.algorithm,
+            response
+        );
+        if let Some(opaque) = &challenge.opaque {
+            header.push_str(&format!(", opaque={}", quote(opaque)));
+        }
+        if let Some(qop) = qop {
+            header.push_str(&format!(", qop={}, nc={}, cnonce=\"{}\"", qop, nc, cnonce));
+        }
+        let mut header: HeaderValue = header.parse()?;
+        header.set_sensitive(true);
+        req.headers_mut().insert(AUTHORIZATION, header);
+        Ok(true)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::testing::{self, Response};
+    use reqwest::Client;
+
+    fn challenge(algorithm: &str) -> Challenge {
+        Challenge::parse(&format!(
+            r#"Digest realm="http-auth@example.org", qop="auth, auth-int", algorithm={},
+            nonce="7ypf/xlj9XXwfDPEoM4URrv/xwf94BcCAzFZH4GiTo0v",
+            opaque="FQhe/qaU925kfnzjCev0ciny7QMkPqMAFRtzCUYo5tdS""#,
+            algorithm
+        ))
+        .unwrap()
+    }
+
+    #[test]
+    fn test_parse_challenge() {
+        let c = challenge("MD5");
+        assert_eq!(c.realm, "http-auth@example.org");
+        assert_eq!(c.qop(false), Some("auth"));
+        assert_eq!(
+            c.opaque.as_deref(),
+            Some("FQhe/qaU925kfnzjCev0ciny7QMkPqMAFRtzCUYo5tdS")
+        );
+        assert_eq!(Challenge::parse(r#"Basic realm="x""#), None);
+        assert_eq!(
+            parse_params(r#"a="x \"y\", z", b=1"#),
+            [("a".into(), r#"x "y", z"#.into()), ("b".into(), "1".into())]
+        );
+    }
+
+    #[test]
+    fn test_response() {
+        // RFC 7616 section 3.9.1
+        let digest = Digest::new("Mufasa".into(), "Circle of Life".into());
+        let cnonce = "f2/wE4q74E6zIJEtWaHKaf5wv/H5QzzpXusqGemxURZJ";
+        let response = |algorithm| {
+            digest
+                .response(
+                    &challenge(algorithm),
+                    "GET",
+                    "/dir/index.html",
+                    Some("auth"),
+                    "00000001",
+                    cnonce,
+                    b"",
+                )
+                .unwrap()
+        };
+        assert_eq!(response("MD5"), "8ca523f5e9506fed4657c9700eebdbec");
+        assert_eq!(
+            response("SHA-256"),
+            "753927fa0e85d155564e2e272a28d1802ca10daf4496794697cf8db5856cb6c1"
+        );
+        assert!(challenge("SHA-512-256").hash(b"").is_err());
+    }
+
+    /// answers with a challenge until a request carries a valid digest for user:secret.
+    fn stub(req: &testing::Request) -> Response {
+        let params = match req
+            .header("authorization")
+            .and_then(|a| a.strip_prefix("Digest "))
+        {
+            Some(params) => parse_params(params),
+            None => {
+                return Response::new(401).header(
+                    "WWW-Authenticate",
+                    r#"Digest realm="stub", nonce="n0nce", qop="auth", opaque="op""#,
+                )
+            }
+        };
+        let get = |k: &str| params.iter().find(|(p, _)| p == k).unwrap().1.as_str();
+        let c = Challenge::parse("Digest realm=stub, nonce=n0nce").unwrap();
+        let expected = Digest::new("user".into(), "secret".into())
+            .response(
+                &c,
+                &req.method,
+                get("uri"),
+                Some("auth"),
+                get("nc"),
+                get("cnonce"),
+                b"",
+            )
+            .unwrap();
+        match get("response") == expected && get("opaque") == "op" {
+            true => Response::new(200),
+            false => Response::new(403),
+        }
+    }
+
+    #[tokio::test]
+    async fn test_digest_against_stub_server() {
+        let url = format!("{}/dir/index.html?x=1", testing::serve(stub).await);
+        let client = Client::new();
+        for (password, status) in [("secret", 200), ("wrong", 403)] {
+            let digest = Digest::new("user".into(), password.into());
+            let mut req = client.get(&url).build().unwrap();
+            digest.apply(&mut req).unwrap();
+            let mut retry = req.try_clone().unwrap();
+            let resp = client.execute(req).await.unwrap();
+            assert_eq!(resp.status(), 401);
+            assert!(digest.respond(&mut retry, &resp).unwrap());
+            assert_eq!(client.execute(retry).await.unwrap().status(), status);
+        }
+    }
+}
@@ -0,0 +1,225 @@
+use anyhow::{anyhow, Context, Result};
+use clap::{Parser, ValueEnum};
+use reqwest::{Client, Request, Response};
+use serde::{Deserialize, Serialize};
+
+use crate::config::config_dir;
+
+mod basic;
+mod bearer;
+mod digest;
+mod netrc;
+mod oauth2;
+mod sigv4;
+
+pub use basic::Basic;
+pub use bearer::Bearer;
+pub use digest::Digest;
+pub use oauth2::OAuth2;
+pub use sigv4::{Credentials, SigV4};
+
+/// an authentication scheme. a new one implements this and gets an `AuthType`
+/// variant which `AuthOpts::plugin` builds it from.
+pub trait Auth {
+    /// add the credentials to the request before it is sent.
+    fn apply(&self, req: &mut Request) -> Result<()>;
+
+    /// answer the 401 challenge in `resp` by updating `req` for a retry, `false`
+    /// when the scheme has nothing to answer with.
+    fn respond(&self, _req: &mut Request, _resp: &Response) -> Result<bool> {
+        Ok(false)
+    }
+}
+
+/// the schemes for `--auth-type`.
+#[derive(ValueEnum, Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum AuthType {
+    Basic,
+    Digest,
+    Bearer,
+    /// AWS Signature Version 4, credentials come from --auth as key:secret, the
+    /// AWS_ACCESS_KEY_ID and AWS_SECRET_ACCESS_KEY env vars or ~/.aws/credentials
+    AwsSigv4,
+    /// an OAuth2 access token from --oauth2-token-url, --auth is client_id:client_secret
+    Oauth2,
+}
+
+/// --auth and --auth-type as they are kept in a session.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct SavedAuth {
+    #[serde(rename = "type")]
+    pub auth_type: AuthType,
+    pub raw_auth: String,
+}
+
+/// authentication options shared by every method.
+#[derive(Parser, Debug, Clone)]
+pub struct AuthOpts {
+    /// credentials as user:pass, user alone to be prompted for the password, or the token
+    /// for --auth-type=bearer
+    #[clap(short, long)]
+    auth: Option<String>,
+    /// the scheme for --auth, defaults to basic
+    #[clap(long, value_enum)]
+    auth_type: Option<AuthType>,
+    /// the region to sign for with --auth-type=aws-sigv4, defaults to $AWS_REGION or
+    /// the one in the host name
+    #[clap(long)]
+    aws_region: Option<String>,
+    /// the service to sign for with --auth-type=aws-sigv4, defaults to the one in the host name
+    #[clap(long)]
+    aws_service: Option<String>,
+    /// the token endpoint for --auth-type=oauth2
+    #[clap(long)]
+    oauth2_token_url: Option<String>,
+    /// the scope to ask for with --auth-type=oauth2
+    #[clap(long)]
+    oauth2_scope: Option<String>,
+    /// get the token with the refresh-token flow instead of client credentials
+    #[clap(long)]
+    oauth2_refresh_token: Option<String>,
+    /// don't take credentials for the host from ~/.netrc or $NETRC when --auth is missing
+    #[clap(long)]
+    ignore_netrc: bool,
+}
+
+impl AuthOpts {
+    /// --auth and --auth-type to keep in a session.
+    pub fn saved(&self) -> Option<SavedAuth> {
+        Some(SavedAuth {
+            auth_type: self.auth_type.unwrap_or(AuthType::Basic),
+            raw_auth: self.auth.clone()?,
+        })
+    }
+
+    /// these options, with the auth of a session when neither --auth nor --auth-type is given.
+    pub fn or_saved(&self, saved: Option<&SavedAuth>) -> Self {
+        let mut opts = self.clone();
+        if let (None, None, Some(saved)) = (&self.auth, self.auth_type, saved) {
+            opts.auth = Some(saved.raw_auth.clone());
+            opts.auth_type = Some(saved.auth_type);
+        }
+        opts
+    }
+
+    /// the plugin for --auth or the netrc entry of `host`, a missing password is prompted
+    /// for and OAuth2 tokens are fetched with `client` up front.
+    pub async fn plugin(
+        &self,
+        client: &Client,
+        host: Option<&str>,
+    ) -> Result<Option<Box<dyn Auth>>> {
+        let auth_type = self.auth_type.unwrap_or(AuthType::Basic);
+        let netrc = match (&self.auth, auth_type) {
+            (None, AuthType::Basic | AuthType::Digest) if !self.ignore_netrc => {
+                host.and_then(netrc::lookup)
+            }
+            _ => None,
+        };
+        let credentials = match (&self.auth, auth_type) {
+            (Some(credentials), _) => credentials,
+            // signing credentials can come from the environment instead
+            (None, AuthType::AwsSigv4) => "",
+            (None, _) if netrc.is_some() => "",
+            (None, _) if self.auth_type.is_some() => {
+                return Err(anyhow!("--auth-type needs --auth"));
+            }
+            (None, _) => return Ok(None),
+        };
+        let plugin: Box<dyn Auth> = match auth_type {
+            AuthType::Basic => {
+                let (user, password) = netrc.map_or_else(|| user_password(credentials), Ok)?;
+                Box::new(Basic::new(&user, &password)?)
+            }
+            AuthType::Digest => {
+                let (user, password) = netrc.map_or_else(|| user_password(credentials), Ok)?;
+                Box::new(Digest::new(user, password))
+            }
+            AuthType::Bearer => Box::new(Bearer::new(credentials)?),
+            AuthType::AwsSigv4 => {
+                let credentials = match credentials.split_once(':') {
+                    Some((access_key, secret_key)) => Credentials {
+                        access_key: access_key.into(),
+                        secret_key: secret_key.into(),
+                        session_token: None,
+                    },
+                    None => Credentials::load()?,
+                };
+                Box::new(SigV4::new(
+                    credentials,
+                    self.aws_region.clone(),
+                    self.aws_service.clone(),
+                ))
+            }
+            AuthType::Oauth2 => {
+                let (client_id, client_secret) = user_password(credentials)?;
+                let oauth2 = OAuth2 {
+                    token_url: self
+                        .oauth2_token_url
+                        .clone()
+                        .ok_or_else(|| anyhow!("--auth-type=oauth2 needs --oauth2-token-url"))?,
+                    client_id,
+                    client_secret,
+                    scope: self.oauth2_scope.clone(),
+                    refresh_token: self.oauth2_refresh_token.clone(),
+                    cache: config_dir().map(|dir| dir.join("oauth2_tokens.json")),
+                };
+                Box::new(Bearer::new(&oauth2.access_token(client).await?)?)
+            }
+        };
+        Ok(Some(plugin))
+    }
+}
+
+/// split `user:pass` on the first colon, without one the password is read from the terminal.
+fn user_password(credentials: &str) -> Result<(String, String)> {
+    if let Some((user, password)) = credentials.split_once(':') {
+        return Ok((user.into(), password.into()));
+    }
+    let password = rpassword::prompt_password(format!("Password for {}: ", credentials))
+        .context("Failed to read the password")?;
+    Ok((credentials.into(), password))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::testing;
+
+    fn opts(args: &[&str]) -> AuthOpts {
+        testing::parse(args)
+    }
+
+    #[test]
+    fn test_user_password() {
+        assert_eq!(
+            user_password("user:pa:ss").unwrap(),
+            ("user".into(), "pa:ss".into())
+        );
+        assert_eq!(user_password("user:").unwrap(), ("user".into(), "".into()));
+    }
+
+    #[tokio::test]
+    async fn test_plugin() {
+        let client = Client::new();
+        let plugin = |args: &'static [&'static str]| {
+            let client = client.clone();
+            async move { opts(args).plugin(&client, None).await }
+        };
+        assert!(plugin(&[]).await.unwrap().is_none());
+        assert!(plugin(&["-a", "u:p"]).await.unwrap().is_some());
+        assert!(plugin(&["-a", "token", "--auth-type", "bearer"])
+            .await
+            .unwrap()
+            .is_some());
+        assert!(plugin(&["--auth-type", "digest"]).await.is_err());
+        assert!(plugin(&["-a", "AKID:secret", "--auth-type", "aws-sigv4"])
+            .await
+            .unwrap()
+            .is_some());
+        assert!(plugin(&["-a", "id:secret", "--auth-type", "oauth2"])
+            .await
+            .is_err());
+    }
+}
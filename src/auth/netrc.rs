@@ -0,0 +1,106 @@
+use std::{env, fs, path::PathBuf};
+
+/// `$NETRC`, or `.netrc` (`_netrc` on Windows) in the home directory.
+fn path() -> Option<PathBuf> {
+    if let Some(path) = env::var_os("NETRC") {
+        return Some(path.into());
+    }
+    let name = if cfg!(windows) { "_netrc" } else { ".netrc" };
+    dirs::home_dir().map(|home| home.join(name))
+}
+
+/// the login and password for `host` from the netrc file.
+pub fn lookup(host: &str) -> Option<(String, String)> {
+    let text = fs::read_to_string(path()?).ok()?;
+    find(&text, host)
+}
+
+/// the `machine` entry for `host`, or else the `default` one. `#` comments and
+/// `macdef` bodies, which run to the next blank line, are skipped.
+fn find(text: &str, host: &str) -> Option<(String, String)> {
+    let mut words = Vec::new();
+    let mut in_macdef = false;
+    for line in text.lines() {
+        if in_macdef {
+            in_macdef = !line.trim().is_empty();
+            continue;
+        }
+        for word in line.split_whitespace() {
+            if word.starts_with('#') {
+                break;
+            }
+            if word == "macdef" {
+                in_macdef = true;
+                break;
+            }
+            words.push(word);
+        }
+    }
+
+    // (machine, login, password), the machine is `None` for `default`
+    let mut entries: Vec<(Option<&str>, Option<&str>, Option<&str>)> = Vec::new();
+    let mut words = words.into_iter();
+    while let Some(word) = words.next() {
+        match word {
+            "machine" => entries.push((Some(words.next()?), None, None)),
+            "default" => entries.push((None, None, None)),
+            "login" => {
+                let login = words.next();
+                if let Some(entry) = entries.last_mut() {
+                    entry.1 = login;
+                }
+            }
+            "password" => {
+                let password = words.next();
+                if let Some(entry) = entries.last_mut() {
+                    entry.2 = password;
+                }
+            }
+            "account" => {
+                words.next();
+            }
+            _ => {}
+        }
+    }
+    let (_, login, password) = entries
+        .iter()
+        .find(|(machine, _, _)| *machine == Some(host))
+        .or_else(|| entries.iter().find(|(machine, _, _)| machine.is_none()))?;
+    Some((login.unwrap_or_default().into(), (*password)?.into()))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_find() {
+        let text = "\
+# work
+machine api.example.com login alice password s3cret
+machine other.example.com
+    login bob
+    account x
+    password hunter2
+macdef init
+machine evil.example.com login mallory password nope
+
+default login anon password guest
+";
+        let find = |host| find(text, host);
+        assert_eq!(
+            find("api.example.com"),
+            Some(("alice".into(), "s3cret".into()))
+        );
+        assert_eq!(
+            find("other.example.com"),
+            Some(("bob".into(), "hunter2".into()))
+        );
+        assert_eq!(
+            find("evil.example.com"),
+            Some(("anon".into(), "guest".into()))
+        );
+        assert_eq!(super::find("machine a login b", "a"), None);
+        assert_eq!(super::find("", "a"), None);
+    }
+}
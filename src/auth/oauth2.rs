@@ -0,0 +1,231 @@
+use std::collections::BTreeMap;
+use std::path::PathBuf;
+
+use anyhow::{anyhow, Context, Result};
+use reqwest::Client;
+use serde::{Deserialize, Serialize};
+
+use crate::util::{now, read_optional, write_private};
+
+/// tokens are fetched again this many seconds before they expire.
+const EXPIRY_MARGIN: u64 = 30;
+
+/// the client-credentials and refresh-token flows of RFC 6749 against a token endpoint.
+pub struct OAuth2 {
+    pub token_url: String,
+    pub client_id: String,
+    pub client_secret: String,
+    pub scope: Option<String>,
+    /// use the refresh-token flow with this token instead of client credentials.
+    pub refresh_token: Option<String>,
+    /// where tokens are kept between runs, `None` doesn't cache them.
+    pub cache: Option<PathBuf>,
+}
+
+/// an access token as it is cached.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+struct Token {
+    access_token: String,
+    #[serde(default)]
+    refresh_token: Option<String>,
+    /// seconds since the epoch, unknown when the server sent no `expires_in`.
+    #[serde(default)]
+    expires_at: Option<u64>,
+}
+
+impl Token {
+    /// tokens without a known expiry are never reused.
+    fn fresh(&self, now: u64) -> bool {
+        self.expires_at
+            .is_some_and(|expires_at| now + EXPIRY_MARGIN < expires_at)
+    }
+}
+
+#[derive(Deserialize)]
+struct TokenResponse {
+    access_token: String,
+    expires_in: Option<u64>,
+    refresh_token: Option<String>,
+}
+
+impl OAuth2 {
+    /// a cached token until it expires, then one from the refresh-token flow when there is a
+    /// refresh token, or else from the client-credentials flow.
+    pub async fn access_token(&self, client: &Client) -> Result<String> {
+        let mut tokens = self.load_cache()?;
+        let key = format!(
+            "{} {} {}",
+            self.token_url,
+            self.client_id,
+            self.scope.as_deref().unwrap_or_default()
+        );
+        let cached = tokens.get(&key);
+        if let Some(token) = cached.filter(|t| t.fresh(now())) {
+            return Ok(token.access_token.clone());
+        }
+
+        let refresh_token = self
+            .refresh_token
+            .clone()
+            .or_else(|| cached.and_then(|t| t.refresh_token.clone()));
+        let token = match refresh_token {
+            Some(refresh_token) => {
+                let refreshed = self
+                    .fetch(
+                        client,
+                        &[
+                            ("grant_type", "refresh_token"),
+                            ("refresh_token", &refresh_token),
+                        ],
+                        Some(&refresh_token),
+                    )
+                    .await;
+                match refreshed {
+                    Ok(token) => token,
+                    // a cached refresh token may have been revoked, start over
+                    Err(_) if self.refresh_token.is_none() => {
+                        self.client_credentials(client).await?
+                    }
+                    Err(e) => return Err(e),
+                }
+            }
+            None => self.client_credentials(client).await?,
+        };
+
+        tokens.insert(key, token.clone());
+        self.save_cache(&tokens)?;
+        Ok(token.access_token)
+    }
+
+    async fn client_credentials(&self, client: &Client) -> Result<Token> {
+        self.fetch(client, &[("grant_type", "client_credentials")], None)
+            .await
+    }
+
+    /// post a token request, the client authenticates with basic auth.
+    async fn fetch(
+        &self,
+        client: &Client,
+        params: &[(&str, &str)],
+        refresh_token: Option<&str>,
+    ) -> Result<Token> {
+        let mut form = params.to_vec();
+        if let Some(scope) = &self.scope {
+            form.push(("scope", scope));
+        }
+        let resp = client
+            .post(&self.token_url)
+            .basic_auth(&self.client_id, Some(&self.client_secret))
+            .form(&form)
+            .send()
+            .await
+            .with_context(|| format!("Token request to {} failed", self.token_url))?;
+        let status = resp.status();
+        let body = resp.text().await?;
+        if !status.is_success() {
+            return Err(anyhow!(
+                "Token request to {} failed: {} {}",
+                self.token_url,
+                status,
+                body
+            ));
+        }
+        let token: TokenResponse = serde_json::from_str(&body)
+            .with_context(|| format!("Invalid token response from {}", self.token_url))?;
+        Ok(Token {
+            access_token: token.access_token,
+            // servers may keep the refresh token the same without sending it again
+            refresh_token: token.refresh_token.or(refresh_token.map(String::from)),
+            expires_at: token.expires_in.map(|expires_in| now() + expires_in),
+        })
+    }
+
+    fn load_cache(&self) -> Result<BTreeMap<String, Token>> {
+        let path = match &self.cache {
+            Some(path) => path,
+            None => return Ok(BTreeMap::new()),
+        };
+        // a broken cache is only a missed shortcut
+        let text = read_optional(path)?.unwrap_or_default();
+        Ok(serde_json::from_str(&text).unwrap_or_default())
+    }
+
+    fn save_cache(&self, tokens: &BTreeMap<String, Token>) -> Result<()> {
+        match &self.cache {
+            Some(path) => write_private(path, &serde_json::to_string_pretty(tokens)?),
+            None => Ok(()),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::testing::{self, Response};
+    use std::sync::{Arc, Mutex};
+
+    #[test]
+    fn test_fresh() {
+        let token = |expires_at| Token {
+            access_token: "t".into(),
+            refresh_token: None,
+            expires_at,
+        };
+        assert!(token(Some(1000)).fresh(900));
+        assert!(!token(Some(1000)).fresh(990));
+        assert!(!token(None).fresh(0));
+    }
+
+    /// a token endpoint which records the request bodies it gets.
+    async fn stub(bodies: Arc<Mutex<Vec<String>>>) -> String {
+        let url = testing::serve(move |req| {
+            let n = {
+                let mut bodies = bodies.lock().unwrap();
+                bodies.push(String::from_utf8(req.body.clone()).unwrap());
+                bodies.len()
+            };
+            let json = format!(
+                r#"{{"access_token":"t{}","token_type":"Bearer","expires_in":3600,"refresh_token":"r{}"}}"#,
+                n, n
+            );
+            Response::new(200)
+                .header("Content-Type", "application/json")
+                .body(json)
+        })
+        .await;
+        format!("{}/token", url)
+    }
+
+    #[tokio::test]
+    async fn test_access_token() {
+        let bodies = Arc::new(Mutex::new(Vec::new()));
+        let cache = testing::temp_path("oauth2.json");
+        let oauth2 = OAuth2 {
+            token_url: stub(bodies.clone()).await,
+            client_id: "id".into(),
+            client_secret: "secret".into(),
+            scope: Some("read".into()),
+            refresh_token: None,
+            cache: Some(cache.clone()),
+        };
+        let client = Client::new();
+
+        assert_eq!(oauth2.access_token(&client).await.unwrap(), "t1");
+        // served from the cache
+        assert_eq!(oauth2.access_token(&client).await.unwrap(), "t1");
+
+        let mut tokens = oauth2.load_cache().unwrap();
+        tokens.values_mut().for_each(|t| t.expires_at = Some(0));
+        oauth2.save_cache(&tokens).unwrap();
+        assert_eq!(oauth2.access_token(&client).await.unwrap(), "t2");
+        std::fs::remove_file(&cache).unwrap();
+
+        assert_eq!(
+            *bodies.lock().unwrap(),
+            [
+                "grant_type=client_credentials&scope=read",
+                "grant_type=refresh_token&refresh_token=r1&scope=read"
+            ]
+        );
+    }
+}
@@ -0,0 +1,454 @@
+use std::{env, fs};
+
+use anyhow::{anyhow, Context, Result};
+use hmac::{Hmac, Mac};
+use percent_encoding::{percent_decode_str, utf8_percent_encode, AsciiSet, NON_ALPHANUMERIC};
+use reqwest::header::{HeaderValue, AUTHORIZATION};
+use reqwest::Request;
+use sha2::{Digest as _, Sha256};
+
+use super::Auth;
+use crate::util;
+
+/// everything but the unreserved characters, as AWS encodes them.
+const AWS_ENCODE: &AsciiSet = &NON_ALPHANUMERIC
+    .remove(b'-')
+    .remove(b'_')
+    .remove(b'.')
+    .remove(b'~');
+
+/// AWS credentials, a session token comes with temporary ones.
+#[derive(Debug, PartialEq)]
+pub struct Credentials {
+    pub access_key: String,
+    pub secret_key: String,
+    pub session_token: Option<String>,
+}
+
+impl Credentials {
+    /// `AWS_ACCESS_KEY_ID` and `AWS_SECRET_ACCESS_KEY`, or else the `AWS_PROFILE` section,
+    /// `default` when unset, of the shared credentials file.
+    pub fn load() -> Result<Self> {
+        if let (Ok(access_key), Ok(secret_key)) = (
+            env::var("AWS_ACCESS_KEY_ID"),
+            env::var("AWS_SECRET_ACCESS_KEY"),
+        ) {
+            return Ok(Self {
+                access_key,
+                secret_key,
+                session_token: env::var("AWS_SESSION_TOKEN").ok(),
+            });
+        }
+        let path = match env::var_os("AWS_SHARED_CREDENTIALS_FILE") {
+            Some(path) => path.into(),
+            None => dirs::home_dir()
+                .ok_or_else(|| anyhow!("No AWS credentials found"))?
+                .join(".aws/credentials"),
+        };
+        let profile = env::var("AWS_PROFILE").unwrap_or_else(|_| "default".into());
+        let text = fs::read_to_string(&path)
+            .with_context(|| format!("No AWS credentials found in {}", path.display()))?;
+        Self::from_profile(&text, &profile).ok_or_else(|| {
+            anyhow!(
+                "No AWS credentials for profile {} in {}",
+                profile,
+                path.display()
+            )
+        })
+    }
+
+    /// the keys of `[profile]` in an ini style credentials file.
+    fn from_profile(text: &str, profile: &str) -> Option<Self> {
+        let mut section = None;
+        let (mut access_key, mut secret_key, mut session_token) = (None, None, None);
+        for line in text.lines().map(str::trim) {
+            if line.starts_with('#') || line.starts_with(';') {
+                continue;
+            }
+            if let Some(name) = line.strip_prefix('[').and_then(|l| l.strip_suffix(']')) {
+                section = Some(name.trim());
+                continue;
+            }
+            let (key, value) = match line.split_once('=') {
+                Some((key, value)) if section == Some(profile) => (key.trim(), value.trim()),
+                _ => continue,
+            };
+            match key {
+                "aws_access_key_id" => access_key = Some(value.to_string()),
+                "aws_secret_access_key" => secret_key = Some(value.to_string()),
+                "aws_session_token" => session_token = Some(value.to_string()),
+                _ => {}
+            }
+        }
+        Some(Self {
+            access_key: access_key?,
+            secret_key: secret_key?,
+            session_token,
+        })
+    }
+}
+
+/// AWS Signature Version 4, the region and service are taken from
+/// `service.region.amazonaws.com` hosts unless they are given.
+pub struct SigV4 {
+    credentials: Credentials,
+    region: Option<String>,
+    service: Option<String>,
+}
+
+impl SigV4 {
+    pub fn new(credentials: Credentials, region: Option<String>, service: Option<String>) -> Self {
+        let region = region
+            .or_else(|| env::var("AWS_REGION").ok())
+            .or_else(|| env::var("AWS_DEFAULT_REGION").ok());
+        Self {
+            credentials,
+            region,
+            service,
+        }
+    }
+
+    /// sign `req` at `amz_date`, e.g. `20150830T123600Z`.
+    fn sign(&self, req: &mut Request, amz_date: &str) -> Result<()> {
+        let url = req.url().clone();
+        let host = match (url.host_str(), url.port()) {
+            (Some(host), Some(port)) => format!("{}:{}", host, port),
+            (Some(host), None) => host.to_string(),
+            (None, _) => return Err(anyhow!("Can't sign a request without a host")),
+        };
+        let (host_service, host_region) = service_region(url.host_str().unwrap_or_default());
+        let region = self
+            .region
+            .as_deref()
+            .or(host_region)
+            .ok_or_else(|| anyhow!("Missing the AWS region, use --aws-region"))?;
+        let service = self
+            .service
+            .as_deref()
+            .or(host_service)
+            .ok_or_else(|| anyhow!("Missing the AWS service, use --aws-service"))?;
+
+        let payload_hash = match req.body().map(|b| b.as_bytes()) {
+            None => hex_sha256(b""),
+            Some(Some(body)) => hex_sha256(body),
+            // multipart forms are streamed and can't be hashed up front
+            Some(None) => "UNSIGNED-PAYLOAD".into(),
+        };
+        let headers = req.headers_mut();
+        headers.insert("x-amz-date", amz_date.parse()?);
+        if service == "s3" {
+            headers.insert("x-amz-content-sha256", payload_hash.parse()?);
+        }
+        if let Some(token) = &self.credentials.session_token {
+            headers.insert("x-amz-security-token", token.parse()?);
+        }
+
+        let (canonical, signed_headers) = canonical_request(req, &host, service, &payload_hash);
+        let scope = format!("{}/{}/{}/aws4_request", &amz_date[..8], region, service);
+        let string_to_sign = format!(
+            "AWS4-HMAC-SHA256\n{}\n{}\n{}",
+            amz_date,
+            scope,
+            hex_sha256(canonical.as_bytes())
+        );
+        let mut key = format!("AWS4{}", self.credentials.secret_key).into_bytes();
+        for part in [&amz_date[..8], region, service, "aws4_request"] {
+            key = hmac_sha256(&key, part.as_bytes());
+        }
+        let signature = hex(&hmac_sha256(&key, string_to_sign.as_bytes()));
+
+        let mut header: HeaderValue = format!(
+            "AWS4-HMAC-SHA256 Credential={}/{}, SignedHeaders={}, Signature={}",
+            self.credentials.access_key, scope, signed_headers, signature
+        )
+        .parse()?;
+        header.set_sensitive(true);
+        req.headers_mut().insert(AUTHORIZATION, header);
+        Ok(())
+    }
+}
+
+impl Auth for SigV4 {
+    fn apply(&self, req: &mut Request) -> Result<()> {
+        self.sign(req, &amz_date(util::now()))
+    }
+}
+
+/// the canonical request and its signed header names. only `host`, `content-type`,
+/// `content-md5` and `x-amz-*` headers are signed, others like `user-agent` or the
+/// `authorization` of an earlier signature may change on the way. S3 signs the path as it
+/// is sent, every other service signs it encoded once more, so `/a%20b` as `/a%2520b`.
+fn canonical_request(
+    req: &Request,
+    host: &str,
+    service: &str,
+    payload_hash: &str,
+) -> (String, String) {
+    let encode = |s: &str| utf8_percent_encode(s, AWS_ENCODE).to_string();
+    let path = req
+        .url()
+        .path()
+        .split('/')
+        .map(|segment| match service {
+            "s3" => encode(&percent_decode_str(segment).decode_utf8_lossy()),
+            _ => encode(segment),
+        })
+        .collect::<Vec<_>>()
+        .join("/");
+
+    let mut query: Vec<(String, String)> = req
+        .url()
+        .query_pairs()
+        .map(|(k, v)| (encode(&k), encode(&v)))
+        .collect();
+    query.sort();
+    let query = query
+        .iter()
+        .map(|(k, v)| format!("{}={}", k, v))
+        .collect::<Vec<_>>()
+        .join("&");
+
+    let mut headers: Vec<(String, String)> = vec![("host".into(), host.into())];
+    for name in req.headers().keys().filter(|name| signed(name.as_str())) {
+        let values: Vec<String> = req
+            .headers()
+            .get_all(name)
+            .iter()
+            .map(|v| {
+                let v = String::from_utf8_lossy(v.as_bytes());
+                v.split_whitespace().collect::<Vec<_>>().join(" ")
+            })
+            .collect();
+        headers.push((name.as_str().into(), values.join(",")));
+    }
+    headers.sort();
+    let signed_headers = headers
+        .iter()
+        .map(|(k, _)| k.as_str())
+        .collect::<Vec<_>>()
+        .join(";");
+    let canonical_headers: String = headers
+        .iter()
+        .map(|(k, v)| format!("{}:{}\n", k, v))
+        .collect();
+
+    let canonical = format!(
+        "{}\n{}\n{}\n{}\n{}\n{}",
+        req.method(),
+        path,
+        query,
+        canonical_headers,
+        signed_headers,
+        payload_hash
+    );
+    (canonical, signed_headers)
+}
+
+fn signed(name: &str) -> bool {
+    matches!(name, "content-type" | "content-md5") || name.starts_with("x-amz-")
+}
+
+/// `(service, region)` from `....service.region.amazonaws.com`.
+fn service_region(host: &str) -> (Option<&str>, Option<&str>) {
+    let labels: Vec<&str> = match host.strip_suffix(".amazonaws.com") {
+        Some(prefix) => prefix.split('.').collect(),
+        None => return (None, None),
+    };
+    match labels[..] {
+        [.., service, region] => (Some(service), Some(region)),
+        // global endpoints like iam.amazonaws.com
+        [service] => (Some(service), Some("us-east-1")),
+        [] => (None, None),
+    }
+}
+
+fn hmac_sha256(key: &[u8], data: &[u8]) -> Vec<u8> {
+    let mut mac = Hmac::<Sha256>::new_from_slice(key).expect("HMAC takes keys of any size");
+    mac.update(data);
+    mac.finalize().into_bytes().to_vec()
+}
+
+fn hex_sha256(data: &[u8]) -> String {
+    hex(&Sha256::digest(data))
+}
+
+fn hex(bytes: &[u8]) -> String {
+    bytes.iter().map(|b| format!("{:02x}", b)).collect()
+}
+
+/// `YYYYMMDDTHHMMSSZ` for seconds since the epoch.
+fn amz_date(secs: u64) -> String {
+    let (days, rem) = ((secs / 86400) as i64, secs % 86400);
+    // civil_from_days, http://howardhinnant.github.io/date_algorithms.html
+    let z = days + 719468;
+    let era = z.div_euclid(146097);
+    let doe = z - era * 146097;
+    let yoe = (doe - doe / 1460 + doe / 36524 - doe / 146096) / 365;
+    let doy = doe - (365 * yoe + yoe / 4 - yoe / 100);
+    let mp = (5 * doy + 2) / 153;
+    let day = doy - (153 * mp + 2) / 5 + 1;
+    let month = if mp < 10 { mp + 3 } else { mp - 9 };
+    let year = yoe + era * 400 + (month <= 2) as i64;
+    format!(
+        "{:04}{:02}{:02}T{:02}{:02}{:02}Z",
+        year,
+        month,
+        day,
+        rem / 3600,
+        rem % 3600 / 60,
+        rem % 60
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use reqwest::{Client, Method};
+
+    /// the credentials, region, service and date of the AWS SigV4 test suite.
+    fn suite() -> SigV4 {
+        let credentials = Credentials {
+            access_key: "AKIDEXAMPLE".into(),
+            secret_key: "wJalrXUtnFEMI/K7MDENG+bPxRfiCYEXAMPLEKEY".into(),
+            session_token: None,
+        };
+        SigV4::new(
+            credentials,
+            Some("us-east-1".into()),
+            Some("service".into()),
+        )
+    }
+
+    fn signature(req: &mut Request) -> String {
+        suite().sign(req, "20150830T123600Z").unwrap();
+        let header = req.headers()[AUTHORIZATION].to_str().unwrap();
+        header.rsplit("Signature=").next().unwrap().to_string()
+    }
+
+    #[test]
+    fn test_get_vanilla() {
+        let mut req = Request::new(
+            Method::GET,
+            "https://example.amazonaws.com/".parse().unwrap(),
+        );
+        let (canonical, signed) = {
+            let mut req = req.try_clone().unwrap();
+            req.headers_mut()
+                .insert("x-amz-date", "20150830T123600Z".parse().unwrap());
+            canonical_request(&req, "example.amazonaws.com", "service", &hex_sha256(b""))
+        };
+        assert_eq!(signed, "host;x-amz-date");
+        assert_eq!(
+            hex_sha256(canonical.as_bytes()),
+            "bb579772317eb040ac9ed261061d46c1f17a8133879d6129b6e1c25292927e63"
+        );
+        assert_eq!(
+            signature(&mut req),
+            "5fa00fa31553b73ebf1942676e86291e8372ff2a2260956d9b8aae1d763fbf31"
+        );
+        let credential = "Credential=AKIDEXAMPLE/20150830/us-east-1/service/aws4_request";
+        assert!(req.headers()[AUTHORIZATION]
+            .to_str()
+            .unwrap()
+            .starts_with(&format!(
+                "AWS4-HMAC-SHA256 {}, SignedHeaders=host;x-amz-date, ",
+                credential
+            )));
+    }
+
+    #[test]
+    fn test_get_vanilla_query_order_key_case() {
+        let url = "https://example.amazonaws.com/?Param2=value2&Param1=value1";
+        let mut req = Request::new(Method::GET, url.parse().unwrap());
+        assert_eq!(
+            signature(&mut req),
+            "b97d918cfa904a5beff61c982a1b6f458b799221646efd99d3219ec94cdf2500"
+        );
+    }
+
+    #[test]
+    fn test_post_x_www_form_urlencoded() {
+        let mut req = Client::new()
+            .post("https://example.amazonaws.com/")
+            .header("content-type", "application/x-www-form-urlencoded")
+            .body("Param1=value1")
+            .build()
+            .unwrap();
+        assert_eq!(
+            signature(&mut req),
+            "ff11897932ad3f4e8b18135d722051e5ac45fc38421b1da7b9d196a0fe09473a"
+        );
+    }
+
+    #[test]
+    fn test_encoded_path() {
+        let req = Request::new(
+            Method::GET,
+            "https://example.amazonaws.com/a%20b/c-d".parse().unwrap(),
+        );
+        let path = |service| {
+            let (canonical, _) = canonical_request(&req, "example.amazonaws.com", service, "");
+            canonical.lines().nth(1).unwrap().to_string()
+        };
+        assert_eq!(path("execute-api"), "/a%2520b/c-d");
+        assert_eq!(path("s3"), "/a%20b/c-d");
+    }
+
+    #[test]
+    fn test_resign() {
+        let mut req = Request::new(
+            Method::GET,
+            "https://example.amazonaws.com/".parse().unwrap(),
+        );
+        req.headers_mut()
+            .insert("user-agent", "minihttpie".parse().unwrap());
+        let first = signature(&mut req);
+        // signing again, e.g. after a redirect, doesn't cover the first signature
+        assert_eq!(signature(&mut req), first);
+        assert!(req.headers()[AUTHORIZATION]
+            .to_str()
+            .unwrap()
+            .contains("SignedHeaders=host;x-amz-date, "));
+    }
+
+    #[test]
+    fn test_from_profile() {
+        let text = "[default]\naws_access_key_id = A\naws_secret_access_key = S\n\n\
+                    [dev]\n# comment\naws_access_key_id=B\naws_secret_access_key=T\naws_session_token=X\n";
+        assert_eq!(
+            Credentials::from_profile(text, "dev").unwrap(),
+            Credentials {
+                access_key: "B".into(),
+                secret_key: "T".into(),
+                session_token: Some("X".into()),
+            }
+        );
+        assert_eq!(
+            Credentials::from_profile(text, "default")
+                .unwrap()
+                .access_key,
+            "A"
+        );
+        assert_eq!(Credentials::from_profile(text, "prod"), None);
+    }
+
+    #[test]
+    fn test_service_region() {
+        assert_eq!(
+            service_region("abc.execute-api.eu-west-1.amazonaws.com"),
+            (Some("execute-api"), Some("eu-west-1"))
+        );
+        assert_eq!(
+            service_region("iam.amazonaws.com"),
+            (Some("iam"), Some("us-east-1"))
+        );
+        assert_eq!(service_region("example.com"), (None, None));
+    }
+
+    #[test]
+    fn test_amz_date() {
+        assert_eq!(amz_date(1440938160), "20150830T123600Z");
+        assert_eq!(amz_date(951782400), "20000229T000000Z");
+        assert_eq!(amz_date(0), "19700101T000000Z");
+    }
+}
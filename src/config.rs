@@ -0,0 +1,81 @@
+use std::{collections::BTreeMap, env, path::PathBuf};
+
+use anyhow::{Context, Result};
+use reqwest::header::{self, HeaderMap, HeaderName, HeaderValue};
+use serde::Deserialize;
+
+use crate::util::read_optional;
+
+/// settings read from `config.json` in the config directory, e.g.
+/// `{"default_headers": {"X-POWERED": null, "Accept": "application/json"}}`
+#[derive(Debug, Default, Deserialize)]
+#[serde(default)]
+pub struct Config {
+    /// headers sent with every request, `null` removes a built-in default.
+    pub default_headers: BTreeMap<String, Option<String>>,
+}
+
+/// `$MINIHTTPIE_CONFIG_DIR`, or `minihttpie` in the platform config directory.
+pub fn config_dir() -> Option<PathBuf> {
+    match env::var_os("MINIHTTPIE_CONFIG_DIR") {
+        Some(dir) => Some(dir.into()),
+        None => dirs::config_dir().map(|dir| dir.join("minihttpie")),
+    }
+}
+
+impl Config {
+    /// load the config file, a missing file gives the defaults.
+    pub fn load() -> Result<Self> {
+        let path = match config_dir() {
+            Some(dir) => dir.join("config.json"),
+            None => return Ok(Self::default()),
+        };
+        match read_optional(&path)? {
+            Some(text) => serde_json::from_str(&text)
+                .with_context(|| format!("Invalid config file {}", path.display())),
+            None => Ok(Self::default()),
+        }
+    }
+
+    /// the built-in default headers with the configured ones applied on top.
+    pub fn default_headers(&self) -> Result<HeaderMap> {
+        let mut headers = HeaderMap::new();
+        headers.insert("X-POWERED", HeaderValue::from_static("Rust"));
+        headers.insert(
+            header::USER_AGENT,
+            concat!("minihttpie/", env!("CARGO_PKG_VERSION")).parse()?,
+        );
+        for (name, value) in self.default_headers.iter() {
+            let name: HeaderName = name.parse()?;
+            match value {
+                Some(value) => headers.insert(name, value.parse()?),
+                None => headers.remove(name),
+            };
+        }
+        Ok(headers)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_default_headers() {
+        let headers = Config::default().default_headers().unwrap();
+        assert_eq!(headers["x-powered"], "Rust");
+        assert_eq!(
+            headers[header::USER_AGENT],
+            format!("minihttpie/{}", env!("CARGO_PKG_VERSION"))
+        );
+
+        let config: Config = serde_json::from_str(
+            r#"{"default_headers": {"X-POWERED": null, "User-Agent": "ci", "Accept": "*/*"}}"#,
+        )
+        .unwrap();
+        let headers = config.default_headers().unwrap();
+        assert!(headers.get("x-powered").is_none());
+        assert_eq!(headers[header::USER_AGENT], "ci");
+        assert_eq!(headers[header::ACCEPT], "*/*");
+    }
+}
@@ -0,0 +1,405 @@
+use std::{
+    path::PathBuf,
+    time::{Duration, UNIX_EPOCH},
+};
+
+use anyhow::{anyhow, Result};
+use clap::Parser;
+use reqwest::{
+    header::{self, HeaderMap},
+    Url,
+};
+use serde::{Deserialize, Serialize};
+
+use crate::util::{now, read_optional, write_private};
+
+/// cookie options shared by every method.
+#[derive(Parser, Debug)]
+pub struct CookieOpts {
+    /// read cookies from a Netscape/curl cookie file and save the ones set by the response
+    /// back to it
+    #[clap(long)]
+    cookie_jar: Option<PathBuf>,
+    /// send a cookie given as name=value, can be repeated
+    #[clap(long = "cookie", parse(try_from_str = parse_cookie))]
+    cookies: Vec<String>,
+}
+
+fn parse_cookie(s: &str) -> Result<String> {
+    match s.split_once('=') {
+        Some((name, _)) if !name.trim().is_empty() => Ok(s.trim().into()),
+        _ => Err(anyhow!("Invalid cookie {}, expected name=value", s)),
+    }
+}
+
+impl CookieOpts {
+    /// the --cookie-jar file, a missing one starts out empty.
+    pub fn jar(&self) -> Result<Option<Jar>> {
+        let path = match &self.cookie_jar {
+            Some(path) => path.clone(),
+            None => return Ok(None),
+        };
+        let cookies = match read_optional(&path)? {
+            Some(text) => read_netscape(&text),
+            None => Vec::new(),
+        };
+        Ok(Some(Jar { path, cookies }))
+    }
+
+    /// add the cookies of the jar and --cookie to the Cookie header.
+    pub fn apply(&self, jar: Option<&Jar>, headers: &mut HeaderMap, url: &Url) -> Result<()> {
+        if let Some(cookie) = jar.and_then(|jar| header(&jar.cookies, url, now())) {
+            merge_header(headers, &cookie)?;
+        }
+        for cookie in &self.cookies {
+            merge_header(headers, cookie)?;
+        }
+        Ok(())
+    }
+}
+
+/// add the `name=value` pairs of `cookie` to the Cookie header, replacing those with
+/// the same name.
+pub fn merge_header(headers: &mut HeaderMap, cookie: &str) -> Result<()> {
+    let name = |pair: &str| {
+        pair.split('=')
+            .next()
+            .unwrap_or_default()
+            .trim()
+            .to_string()
+    };
+    let mut pairs: Vec<String> = headers
+        .get(header::COOKIE)
+        .map(|v| String::from_utf8_lossy(v.as_bytes()).into_owned())
+        .unwrap_or_default()
+        .split(';')
+        .map(|pair| pair.trim().to_string())
+        .filter(|pair| !pair.is_empty())
+        .collect();
+    for pair in cookie.split(';').map(str::trim).filter(|p| !p.is_empty()) {
+        pairs.retain(|p| name(p) != name(pair));
+        pairs.push(pair.into());
+    }
+    if !pairs.is_empty() {
+        headers.insert(header::COOKIE, pairs.join("; ").parse()?);
+    }
+    Ok(())
+}
+
+/// cookies kept in a Netscape cookie file.
+pub struct Jar {
+    path: PathBuf,
+    pub cookies: Vec<Cookie>,
+}
+
+impl Jar {
+    pub fn save(&self) -> Result<()> {
+        write_private(&self.path, &write_netscape(&self.cookies))
+    }
+}
+
+/// a cookie as it is stored, see RFC 6265.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Cookie {
+    pub name: String,
+    pub value: String,
+    /// lowercase and without a leading dot.
+    pub domain: String,
+    /// only sent to `domain` itself, not to its subdomains.
+    #[serde(default)]
+    pub host_only: bool,
+    pub path: String,
+    /// seconds since the epoch, `None` for a session cookie.
+    #[serde(default)]
+    pub expires: Option<u64>,
+    #[serde(default)]
+    pub secure: bool,
+    #[serde(default)]
+    pub http_only: bool,
+}
+
+/// the directory of the request path, the default cookie path.
+fn default_path(url: &Url) -> String {
+    let path = url.path();
+    match path.rfind('/') {
+        Some(0) | None => "/".into(),
+        Some(i) => path[..i].into(),
+    }
+}
+
+fn domain_match(host: &str, domain: &str) -> bool {
+    host == domain || (host.ends_with(domain) && host[..host.len() - domain.len()].ends_with('.'))
+}
+
+impl Cookie {
+    /// parse a Set-Cookie header received from `url`, `None` when it is malformed or sets
+    /// a cookie for another domain.
+    pub fn parse(header: &str, url: &Url, now: u64) -> Option<Self> {
+        let host = url.host_str()?.to_ascii_lowercase();
+        let mut parts = header.split(';');
+        let (name, value) = parts.next()?.split_once('=')?;
+        let mut cookie = Self {
+            name: name.trim().into(),
+            value: value.trim().trim_matches('"').into(),
+            domain: host.clone(),
+            host_only: true,
+            path: default_path(url),
+            expires: None,
+            secure: false,
+            http_only: false,
+        };
+        if cookie.name.is_empty() {
+            return None;
+        }
+        let mut max_age = None;
+        for part in parts {
+            let (key, value) = part.split_once('=').unwrap_or((part, ""));
+            let value = value.trim();
+            match key.trim().to_ascii_lowercase().as_str() {
+                "domain" if !value.is_empty() => {
+                    let domain = value.trim_start_matches('.').to_ascii_lowercase();
+                    if !domain_match(&host, &domain) {
+                        return None;
+                    }
+                    cookie.domain = domain;
+                    cookie.host_only = false;
+                }
+                "path" if value.starts_with('/') => cookie.path = value.into(),
+                "expires" => {
+                    if let Ok(time) = httpdate::parse_http_date(value) {
+                        let secs = time.duration_since(UNIX_EPOCH).unwrap_or(Duration::ZERO);
+                        cookie.expires = Some(secs.as_secs());
+                    }
+                }
+                "max-age" => max_age = value.parse::<i64>().ok(),
+                "secure" => cookie.secure = true,
+                "httponly" => cookie.http_only = true,
+                _ => {}
+            }
+        }
+        // Max-Age wins over Expires, zero or less expires the cookie right away
+        if let Some(max_age) = max_age {
+            cookie.expires = Some(match max_age {
+                age if age <= 0 => 0,
+                age => now.saturating_add(age as u64),
+            });
+        }
+        Some(cookie)
+    }
+
+    pub fn expired(&self, now: u64) -> bool {
+        self.expires.is_some_and(|expires| expires <= now)
+    }
+
+    /// whether the cookie is sent with a request to `url`.
+    pub fn matches(&self, url: &Url, now: u64) -> bool {
+        let host = url.host_str().unwrap_or_default().to_ascii_lowercase();
+        let domain = match self.host_only {
+            true => host == self.domain,
+            false => domain_match(&host, &self.domain),
+        };
+        let path = url.path();
+        let path = path == self.path
+            || (path.starts_with(&self.path)
+                && (self.path.ends_with('/') || path[self.path.len()..].starts_with('/')));
+        domain && path && (!self.secure || url.scheme() == "https") && !self.expired(now)
+    }
+}
+
+/// add or replace the cookie with the same name, domain and path, an expired one is removed.
+pub fn store(cookies: &mut Vec<Cookie>, cookie: Cookie, now: u64) {
+    cookies
+        .retain(|c| !(c.name == cookie.name && c.domain == cookie.domain && c.path == cookie.path));
+    if !cookie.expired(now) {
+        cookies.push(cookie);
+    }
+}
+
+/// keep the cookies set by a response from `url`, expired ones are dropped.
+pub fn store_all(cookies: &mut Vec<Cookie>, url: &Url, headers: &HeaderMap) {
+    let now = now();
+    for set_cookie in headers.get_all(header::SET_COOKIE) {
+        let set_cookie = String::from_utf8_lossy(set_cookie.as_bytes());
+        if let Some(cookie) = Cookie::parse(&set_cookie, url, now) {
+            store(cookies, cookie, now);
+        }
+    }
+    cookies.retain(|c| !c.expired(now));
+}
+
+/// the Cookie header for a request to `url`, longer paths first.
+pub fn header(cookies: &[Cookie], url: &Url, now: u64) -> Option<String> {
+    let mut matching: Vec<&Cookie> = cookies.iter().filter(|c| c.matches(url, now)).collect();
+    if matching.is_empty() {
+        return None;
+    }
+    matching.sort_by_key(|c| std::cmp::Reverse(c.path.len()));
+    let pairs: Vec<String> = matching
+        .iter()
+        .map(|c| format!("{}={}", c.name, c.value))
+        .collect();
+    Some(pairs.join("; "))
+}
+
+/// the cookies of a Netscape/curl cookie file, `#HttpOnly_` marks http-only ones.
+fn read_netscape(text: &str) -> Vec<Cookie> {
+    let mut cookies = Vec::new();
+    for line in text.lines() {
+        let (line, http_only) = match line.strip_prefix("#HttpOnly_") {
+            Some(line) => (line, true),
+            None => (line, false),
+        };
+        if line.starts_with('#') || line.trim().is_empty() {
+            continue;
+        }
+        let fields: Vec<&str> = line.split('\t').collect();
+        let [domain, subdomains, path, secure, expires, name, value] = fields[..] else {
+            continue;
+        };
+        let Ok(expires) = expires.parse::<u64>() else {
+            continue;
+        };
+        cookies.push(Cookie {
+            name: name.into(),
+            value: value.into(),
+            domain: domain.trim_start_matches('.').to_ascii_lowercase(),
+            host_only: subdomains != "TRUE",
+            path: path.into(),
+            // curl writes session cookies with a zero expiry
+            expires: (expires != 0).then_some(expires),
+            secure: secure == "TRUE",
+            http_only,
+        });
+    }
+    cookies
+}
+
+fn write_netscape(cookies: &[Cookie]) -> String {
+    let bool = |b: bool| if b { "TRUE" } else { "FALSE" };
+    let mut text = String::from("# Netscape HTTP Cookie File\n# Written by minihttpie.\n\n");
+    for c in cookies {
+        text.push_str(&format!(
+            "{}{}{}\t{}\t{}\t{}\t{}\t{}\t{}\n",
+            if c.http_only { "#HttpOnly_" } else { "" },
+            if c.host_only { "" } else { "." },
+            c.domain,
+            bool(!c.host_only),
+            c.path,
+            bool(c.secure),
+            c.expires.unwrap_or(0),
+            c.name,
+            c.value
+        ));
+    }
+    text
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn url(s: &str) -> Url {
+        s.parse().unwrap()
+    }
+
+    #[test]
+    fn test_parse() {
+        let from = url("https://www.example.com/a/b");
+        let c = Cookie::parse(
+            "id=1; Path=/; Domain=.Example.com; Secure; HttpOnly",
+            &from,
+            0,
+        )
+        .unwrap();
+        assert_eq!((c.name.as_str(), c.value.as_str()), ("id", "1"));
+        assert_eq!(c.domain, "example.com");
+        assert!(!c.host_only && c.secure && c.http_only);
+
+        let c = Cookie::parse("x=y", &from, 0).unwrap();
+        assert_eq!(
+            (c.domain.as_str(), c.path.as_str()),
+            ("www.example.com", "/a")
+        );
+        assert!(c.host_only && c.expires.is_none());
+
+        let c = Cookie::parse("x=y; Expires=Wed, 21 Oct 2015 07:28:00 GMT", &from, 0).unwrap();
+        assert_eq!(c.expires, Some(1445412480));
+        let c = Cookie::parse(
+            "x=y; Expires=Wed, 21 Oct 2015 07:28:00 GMT; Max-Age=60",
+            &from,
+            100,
+        )
+        .unwrap();
+        assert_eq!(c.expires, Some(160));
+        assert!(Cookie::parse("x=y; Max-Age=0", &from, 100)
+            .unwrap()
+            .expired(100));
+
+        assert_eq!(Cookie::parse("x=y; Domain=other.com", &from, 0), None);
+        assert_eq!(Cookie::parse("novalue", &from, 0), None);
+    }
+
+    #[test]
+    fn test_header() {
+        let from = url("http://example.com/");
+        let mut cookies = Vec::new();
+        for set_cookie in [
+            "a=1",
+            "b=2; Path=/api",
+            "c=3; Secure",
+            "d=4; Domain=example.com",
+        ] {
+            store(
+                &mut cookies,
+                Cookie::parse(set_cookie, &from, 0).unwrap(),
+                0,
+            );
+        }
+        store(&mut cookies, Cookie::parse("a=5", &from, 0).unwrap(), 0);
+        assert_eq!(cookies.len(), 4);
+
+        let header = |u| header(&cookies, &url(u), 0);
+        assert_eq!(header("http://example.com/api/x").unwrap(), "b=2; d=4; a=5");
+        assert_eq!(header("https://example.com/").unwrap(), "c=3; d=4; a=5");
+        assert_eq!(header("http://sub.example.com/").unwrap(), "d=4");
+        assert_eq!(header("http://example.com/apix").unwrap(), "d=4; a=5");
+        assert_eq!(header("http://other.com/"), None);
+
+        store(
+            &mut cookies,
+            Cookie::parse("d=; Domain=example.com; Max-Age=0", &from, 0).unwrap(),
+            0,
+        );
+        assert_eq!(cookies.len(), 3);
+    }
+
+    #[test]
+    fn test_netscape() {
+        let text = "# Netscape HTTP Cookie File\n\
+                    .example.com\tTRUE\t/\tFALSE\t0\ttheme\tdark\n\
+                    #HttpOnly_api.example.com\tFALSE\t/v1\tTRUE\t1900000000\tsid\tabc\n\
+                    broken line\n";
+        let cookies = read_netscape(text);
+        assert_eq!(cookies.len(), 2);
+        assert!(!cookies[0].host_only && cookies[0].expires.is_none());
+        assert_eq!(cookies[0].domain, "example.com");
+        assert!(cookies[1].host_only && cookies[1].http_only && cookies[1].secure);
+        assert_eq!(cookies[1].expires, Some(1900000000));
+        assert_eq!(read_netscape(&write_netscape(&cookies)), cookies);
+    }
+
+    #[test]
+    fn test_parse_cookie() {
+        assert_eq!(parse_cookie("a=b=c").unwrap(), "a=b=c");
+        assert!(parse_cookie("novalue").is_err());
+        assert!(parse_cookie("=x").is_err());
+    }
+
+    #[test]
+    fn test_merge_header() {
+        let mut headers = HeaderMap::new();
+        merge_header(&mut headers, "a=1; b=2").unwrap();
+        merge_header(&mut headers, "b=3; c=4").unwrap();
+        assert_eq!(headers[header::COOKIE], "a=1; b=3; c=4");
+    }
+}
@@ -0,0 +1,240 @@
+use std::fs::{self, File, OpenOptions};
+use std::io::{BufWriter, Write};
+use std::path::PathBuf;
+use std::time::Instant;
+
+use anyhow::{Context, Result};
+use clap::Parser;
+use indicatif::{HumanBytes, ProgressBar, ProgressStyle};
+use mime::Mime;
+use percent_encoding::percent_decode_str;
+use reqwest::{
+    header::{self, HeaderMap},
+    Response, StatusCode,
+};
+
+/// download options shared by every method.
+#[derive(Parser, Debug)]
+pub struct DownloadOpts {
+    /// save the response body to a file named after Content-Disposition or the url
+    #[clap(short, long)]
+    download: bool,
+    /// save the response body to this file, implies --download
+    #[clap(short, long)]
+    output: Option<PathBuf>,
+    /// resume a partial download of --output with a Range request
+    #[clap(short = 'c', long = "continue", requires = "output")]
+    resume: bool,
+}
+
+impl DownloadOpts {
+    pub fn enabled(&self) -> bool {
+        self.download || self.output.is_some()
+    }
+
+    /// the size of the partial --output file to resume from.
+    pub fn resume_from(&self) -> Option<u64> {
+        let path = self.output.as_ref().filter(|_| self.resume)?;
+        fs::metadata(path)
+            .ok()
+            .map(|m| m.len())
+            .filter(|&len| len > 0)
+    }
+}
+
+/// whether the answer to resuming from `resume_from` says there is nothing left: a 416
+/// whose `Content-Range: bytes */<size>`, when it is sent, is the size of the file.
+pub fn complete(status: StatusCode, headers: &HeaderMap, resume_from: Option<u64>) -> bool {
+    let offset = match resume_from {
+        Some(offset) if status == StatusCode::RANGE_NOT_SATISFIABLE => offset,
+        _ => return false,
+    };
+    headers
+        .get(header::CONTENT_RANGE)
+        .and_then(|v| {
+            v.to_str()
+                .ok()?
+                .strip_prefix("bytes */")?
+                .trim()
+                .parse()
+                .ok()
+        })
+        .is_none_or(|size: u64| size == offset)
+}
+
+/// say that the --output file was already downloaded in full.
+pub fn report_complete(opts: &DownloadOpts, size: u64) {
+    if let Some(path) = &opts.output {
+        eprintln!(
+            "Already downloaded, {} is complete at {}",
+            path.display(),
+            HumanBytes(size)
+        );
+    }
+}
+
+/// stream the body to disk with a progress bar on stderr.
+pub async fn save(mut resp: Response, opts: &DownloadOpts, resume_from: Option<u64>) -> Result<()> {
+    // a server which ignores the Range header sends the whole body again
+    let offset = resume_from.filter(|_| resp.status() == StatusCode::PARTIAL_CONTENT);
+    let path = match &opts.output {
+        Some(path) => path.clone(),
+        None => unique_path(&filename(&resp)),
+    };
+    let file = match offset {
+        Some(_) => OpenOptions::new().append(true).open(&path),
+        None => File::create(&path),
+    }
+    .with_context(|| format!("Failed to open {}", path.display()))?;
+    let mut writer = BufWriter::new(file);
+
+    let offset = offset.unwrap_or(0);
+    let bar = match resp.content_length() {
+        Some(len) => ProgressBar::new(offset + len).with_style(ProgressStyle::with_template(
+            "{bar:40} {bytes}/{total_bytes} {binary_bytes_per_sec} eta {eta}",
+        )?),
+        None => ProgressBar::new_spinner().with_style(ProgressStyle::with_template(
+            "{spinner} {bytes} {binary_bytes_per_sec}",
+        )?),
+    };
+    bar.set_position(offset);
+
+    let started = Instant::now();
+    while let Some(chunk) = resp.chunk().await? {
+        writer.write_all(&chunk)?;
+        bar.inc(chunk.len() as u64);
+    }
+    writer.flush()?;
+    bar.finish_and_clear();
+
+    eprintln!(
+        "Done. {} in {:.2}s saved to {}",
+        HumanBytes(bar.position() - offset),
+        started.elapsed().as_secs_f64(),
+        path.display()
+    );
+    Ok(())
+}
+
+/// the file name from Content-Disposition, or else the last segment of the url.
+fn filename(resp: &Response) -> String {
+    let from_header = resp
+        .headers()
+        .get(header::CONTENT_DISPOSITION)
+        .and_then(|v| v.to_str().ok())
+        .and_then(content_disposition_filename);
+    if let Some(name) = from_header {
+        return name;
+    }
+
+    let segment = resp
+        .url()
+        .path_segments()
+        .and_then(|mut segments| segments.next_back())
+        .map(|s| percent_decode_str(s).decode_utf8_lossy().into_owned());
+    let name = sanitize(segment.as_deref().unwrap_or_default()).unwrap_or_else(|| "index".into());
+    if name.contains('.') {
+        return name;
+    }
+    let mime: Option<Mime> = resp
+        .headers()
+        .get(header::CONTENT_TYPE)
+        .and_then(|v| v.to_str().ok()?.parse().ok());
+    let ext = mime
+        .as_ref()
+        .and_then(|m| mime_guess::get_mime_extensions(m))
+        .and_then(|exts| exts.first());
+    match ext {
+        Some(ext) => format!("{}.{}", name, ext),
+        None => name,
+    }
+}
+
+/// `filename*=UTF-8''x%20y.zip` is preferred over `filename="x y.zip"`.
+fn content_disposition_filename(value: &str) -> Option<String> {
+    let params: Vec<(&str, &str)> = value
+        .split(';')
+        .filter_map(|param| param.trim().split_once('='))
+        .collect();
+    let extended = params
+        .iter()
+        .find(|(k, _)| k.eq_ignore_ascii_case("filename*"))
+        .and_then(|(_, v)| v.split_once("''"))
+        .map(|(_, v)| percent_decode_str(v).decode_utf8_lossy().into_owned());
+    let plain = || {
+        params
+            .iter()
+            .find(|(k, _)| k.eq_ignore_ascii_case("filename"))
+            .map(|(_, v)| v.trim_matches('"').to_string())
+    };
+    sanitize(&extended.or_else(plain)?)
+}
+
+/// keep only the last path component so a server can't write outside the current directory.
+fn sanitize(name: &str) -> Option<String> {
+    let name = name.rsplit(['/', '\\']).next()?.trim();
+    match name {
+        "" | "." | ".." => None,
+        name => Some(name.to_string()),
+    }
+}
+
+/// `name`, or `name-1`, `name-2`... when the file already exists.
+fn unique_path(name: &str) -> PathBuf {
+    let mut path = PathBuf::from(name);
+    let mut n = 1;
+    while path.exists() {
+        path = PathBuf::from(format!("{}-{}", name, n));
+        n += 1;
+    }
+    path
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_content_disposition_filename() {
+        assert_eq!(
+            content_disposition_filename(r#"attachment; filename="report.pdf""#).as_deref(),
+            Some("report.pdf")
+        );
+        assert_eq!(
+            content_disposition_filename(
+                r#"attachment; filename="fallback.txt"; filename*=UTF-8''na%C3%AFve%20file.txt"#
+            )
+            .as_deref(),
+            Some("naïve file.txt")
+        );
+        assert_eq!(
+            content_disposition_filename(r#"attachment; filename="../../etc/passwd""#).as_deref(),
+            Some("passwd")
+        );
+        assert_eq!(content_disposition_filename("inline"), None);
+    }
+
+    #[test]
+    fn test_complete() {
+        let range = |value: &str| {
+            let mut headers = HeaderMap::new();
+            headers.insert(header::CONTENT_RANGE, value.parse().unwrap());
+            headers
+        };
+        let unsatisfiable = StatusCode::RANGE_NOT_SATISFIABLE;
+        assert!(complete(unsatisfiable, &range("bytes */100"), Some(100)));
+        assert!(complete(unsatisfiable, &HeaderMap::new(), Some(100)));
+        // the file is bigger than what the server has, that is no finished download
+        assert!(!complete(unsatisfiable, &range("bytes */50"), Some(100)));
+        assert!(!complete(unsatisfiable, &HeaderMap::new(), None));
+        assert!(!complete(StatusCode::OK, &HeaderMap::new(), Some(100)));
+    }
+
+    #[test]
+    fn test_sanitize() {
+        assert_eq!(sanitize("a/b/c.zip").as_deref(), Some("c.zip"));
+        assert_eq!(sanitize(r"C:\tmp\x.bin").as_deref(), Some("x.bin"));
+        assert_eq!(sanitize(".."), None);
+        assert_eq!(sanitize(""), None);
+    }
+}
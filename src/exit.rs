@@ -0,0 +1,123 @@
+use std::fmt;
+use std::io;
+use std::process::ExitCode;
+
+use reqwest::StatusCode;
+
+/// exit codes for scripts, the same as HTTPie's where it has them.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Exit {
+    Error = 1,
+    Timeout = 2,
+    /// a 3xx response with --check-status.
+    Redirection = 3,
+    /// a 4xx response with --check-status.
+    ClientError = 4,
+    /// a 5xx response with --check-status.
+    ServerError = 5,
+    TooManyRedirects = 6,
+    Dns = 7,
+    Tls = 8,
+}
+
+impl From<Exit> for ExitCode {
+    fn from(exit: Exit) -> Self {
+        ExitCode::from(exit as u8)
+    }
+}
+
+/// the error of following more than --max-redirects.
+#[derive(Debug)]
+pub struct TooManyRedirects(pub usize);
+
+impl fmt::Display for TooManyRedirects {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "Too many redirects (--max-redirects={})", self.0)
+    }
+}
+
+impl std::error::Error for TooManyRedirects {}
+
+/// the exit code for a response status with --check-status, `None` for success.
+pub fn for_status(status: StatusCode) -> Option<Exit> {
+    match status.as_u16() {
+        300..=399 => Some(Exit::Redirection),
+        400..=499 => Some(Exit::ClientError),
+        500..=599 => Some(Exit::ServerError),
+        _ => None,
+    }
+}
+
+/// the exit code for an error, by the first cause which tells what failed.
+pub fn for_error(err: &anyhow::Error) -> Exit {
+    for cause in err.chain() {
+        if cause.is::<TooManyRedirects>() {
+            return Exit::TooManyRedirects;
+        }
+        if cause.downcast_ref::<io::Error>().is_some_and(is_tls) {
+            return Exit::Tls;
+        }
+        if let Some(e) = cause.downcast_ref::<reqwest::Error>() {
+            if e.is_timeout() {
+                return Exit::Timeout;
+            }
+        }
+        // hyper keeps its resolver error private, it is only known by its message
+        if cause.to_string().starts_with("dns error") {
+            return Exit::Dns;
+        }
+    }
+    Exit::Error
+}
+
+/// whether an io::Error carries a rustls error. they come wrapped in io::Errors, more than
+/// one deep, which skip them as their source.
+fn is_tls(mut err: &io::Error) -> bool {
+    while let Some(inner) = err.get_ref() {
+        if inner.is::<rustls::Error>() {
+            return true;
+        }
+        match inner.downcast_ref::<io::Error>() {
+            Some(inner) => err = inner,
+            None => break,
+        }
+    }
+    false
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use anyhow::{anyhow, Context};
+
+    #[test]
+    fn test_for_status() {
+        assert_eq!(for_status(StatusCode::OK), None);
+        assert_eq!(
+            for_status(StatusCode::MOVED_PERMANENTLY),
+            Some(Exit::Redirection)
+        );
+        assert_eq!(for_status(StatusCode::NOT_FOUND), Some(Exit::ClientError));
+        assert_eq!(for_status(StatusCode::BAD_GATEWAY), Some(Exit::ServerError));
+    }
+
+    #[test]
+    fn test_for_error() {
+        assert_eq!(for_error(&anyhow!("boom")), Exit::Error);
+        let err = Err::<(), _>(TooManyRedirects(3)).context("Request failed");
+        assert_eq!(for_error(&err.unwrap_err()), Exit::TooManyRedirects);
+        let err = io::Error::other(io::Error::new(
+            io::ErrorKind::InvalidData,
+            rustls::Error::DecryptError,
+        ));
+        assert_eq!(for_error(&err.into()), Exit::Tls);
+    }
+
+    #[tokio::test]
+    async fn test_for_dns_error() {
+        let err = reqwest::get("http://nonexistent.invalid/")
+            .await
+            .unwrap_err();
+        assert_eq!(for_error(&err.into()), Exit::Dns);
+    }
+}
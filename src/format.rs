@@ -0,0 +1,176 @@
+use std::str::FromStr;
+
+use anyhow::{anyhow, Result};
+use quick_xml::{events::Event, Reader, Writer};
+use serde::Serialize;
+use serde_json::{ser::PrettyFormatter, Map, Serializer, Value};
+
+/// how bodies are reformatted before highlighting, set with
+/// `--format-options=json.indent:2,json.sort_keys:true,xml.indent:4`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct FormatOptions {
+    pub json_format: bool,
+    pub json_indent: usize,
+    pub json_sort_keys: bool,
+    pub xml_format: bool,
+    pub xml_indent: usize,
+}
+
+impl Default for FormatOptions {
+    fn default() -> Self {
+        Self {
+            json_format: true,
+            json_indent: 4,
+            json_sort_keys: false,
+            xml_format: true,
+            xml_indent: 2,
+        }
+    }
+}
+
+impl FromStr for FormatOptions {
+    type Err = anyhow::Error;
+
+    /// comma separated `key:value` pairs on top of the defaults.
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let mut options = Self::default();
+        for option in s.split(',').map(str::trim).filter(|o| !o.is_empty()) {
+            let err = || anyhow!("Invalid format option {}", option);
+            let (key, value) = option.split_once(':').ok_or_else(err)?;
+            match key {
+                "json.format" => options.json_format = value.parse().map_err(|_| err())?,
+                "json.indent" => options.json_indent = value.parse().map_err(|_| err())?,
+                "json.sort_keys" => options.json_sort_keys = value.parse().map_err(|_| err())?,
+                "xml.format" => options.xml_format = value.parse().map_err(|_| err())?,
+                "xml.indent" => options.xml_indent = value.parse().map_err(|_| err())?,
+                _ => return Err(err()),
+            }
+        }
+        Ok(options)
+    }
+}
+
+fn sort_keys(value: Value) -> Value {
+    match value {
+        Value::Object(map) => {
+            let mut entries: Vec<(String, Value)> = map.into_iter().collect();
+            entries.sort_by(|a, b| a.0.cmp(&b.0));
+            Value::Object(
+                entries
+                    .into_iter()
+                    .map(|(k, v)| (k, sort_keys(v)))
+                    .collect::<Map<_, _>>(),
+            )
+        }
+        Value::Array(items) => Value::Array(items.into_iter().map(sort_keys).collect()),
+        value => value,
+    }
+}
+
+/// reindent a JSON body, `\uXXXX` escapes come out as the characters they stand for.
+/// `None` when the body isn't valid JSON so it can be printed as it is.
+pub fn format_json(body: &str, options: &FormatOptions) -> Option<String> {
+    if !options.json_format {
+        return None;
+    }
+    let mut value: Value = serde_json::from_str(body).ok()?;
+    if options.json_sort_keys {
+        value = sort_keys(value);
+    }
+    let indent = " ".repeat(options.json_indent);
+    let mut out = Vec::new();
+    let mut serializer =
+        Serializer::with_formatter(&mut out, PrettyFormatter::with_indent(indent.as_bytes()));
+    value.serialize(&mut serializer).ok()?;
+    String::from_utf8(out).ok()
+}
+
+/// reindent an XML body, whitespace between elements is dropped.
+/// `None` when the body isn't well formed so it can be printed as it is.
+pub fn format_xml(body: &str, options: &FormatOptions) -> Option<String> {
+    if !options.xml_format {
+        return None;
+    }
+    let mut reader = Reader::from_str(body);
+    let mut writer = Writer::new_with_indent(Vec::new(), b' ', options.xml_indent);
+    loop {
+        match reader.read_event().ok()? {
+            Event::Eof => break,
+            // text with anything but whitespace is kept as it is
+            Event::Text(text) if text.iter().all(u8::is_ascii_whitespace) => {}
+            event => writer.write_event(event).ok()?,
+        }
+    }
+    String::from_utf8(writer.into_inner()).ok()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_format_options() {
+        assert_eq!(
+            "".parse::<FormatOptions>().unwrap(),
+            FormatOptions::default()
+        );
+        let options: FormatOptions = "json.indent:2, json.sort_keys:true".parse().unwrap();
+        assert_eq!(options.json_indent, 2);
+        assert!(options.json_sort_keys && options.json_format);
+        assert!("json.indent:two".parse::<FormatOptions>().is_err());
+        assert!("xml.width:80".parse::<FormatOptions>().is_err());
+        let options: FormatOptions = "xml.format:false".parse().unwrap();
+        assert!(!options.xml_format);
+    }
+
+    #[test]
+    fn test_format_json() {
+        let options = FormatOptions {
+            json_indent: 2,
+            ..FormatOptions::default()
+        };
+        assert_eq!(
+            format_json(
+                r#"{"b":1,"a":["\u4e2d",12345678901234567890123]}"#,
+                &options
+            )
+            .unwrap(),
+            "{\n  \"b\": 1,\n  \"a\": [\n    \"中\",\n    12345678901234567890123\n  ]\n}"
+        );
+
+        let sorted = FormatOptions {
+            json_indent: 0,
+            json_sort_keys: true,
+            ..FormatOptions::default()
+        };
+        assert_eq!(
+            format_json(r#"{"b":{"d":1,"c":2},"a":0}"#, &sorted).unwrap(),
+            "{\n\"a\": 0,\n\"b\": {\n\"c\": 2,\n\"d\": 1\n}\n}"
+        );
+
+        assert_eq!(format_json("{oops", &options), None);
+        let off = FormatOptions {
+            json_format: false,
+            ..FormatOptions::default()
+        };
+        assert_eq!(format_json("{}", &off), None);
+    }
+
+    #[test]
+    fn test_format_xml() {
+        let options = FormatOptions::default();
+        assert_eq!(
+            format_xml(
+                r#"<?xml version="1.0"?><a x="1"><b>text</b>  <c/></a>"#,
+                &options
+            )
+            .unwrap(),
+            "<?xml version=\"1.0\"?>\n<a x=\"1\">\n  <b>text</b>\n  <c/>\n</a>"
+        );
+        assert_eq!(
+            format_xml("<a>\n  <b> two  words </b>\n</a>", &options).unwrap(),
+            "<a>\n  <b> two  words </b>\n</a>"
+        );
+        assert_eq!(format_xml("<a><b></a>", &options), None);
+    }
+}
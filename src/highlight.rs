@@ -0,0 +1,253 @@
+use std::fs;
+use std::path::Path;
+use std::sync::OnceLock;
+
+use anyhow::{anyhow, Result};
+use mime::Mime;
+
+use syntect::easy::HighlightLines;
+use syntect::highlighting::{Theme, ThemeSet};
+use syntect::parsing::{SyntaxDefinition, SyntaxSet, SyntaxSetBuilder};
+use syntect::util::{as_24_bit_terminal_escaped, LinesWithEndings};
+
+use crate::config::config_dir;
+
+/// readable on light and dark terminals, the terminal background is kept.
+pub const DEFAULT_STYLE: &str = "Solarized (light)";
+
+/// syntaxes missing from the syntect defaults.
+const EXTRA_SYNTAXES: &[&str] = &[
+    include_str!("../assets/syntaxes/CSV.sublime-syntax"),
+    include_str!("../assets/syntaxes/GraphQL.sublime-syntax"),
+];
+
+/// syntect extensions by mime essence, `+json`, `+xml` and `+yaml` suffixes are
+/// handled by `syntax_extension`.
+const SYNTAXES: &[(&str, &str)] = &[
+    ("application/json", "json"),
+    ("text/json", "json"),
+    ("application/xml", "xml"),
+    ("text/xml", "xml"),
+    ("text/html", "html"),
+    ("text/css", "css"),
+    ("text/javascript", "js"),
+    ("application/javascript", "js"),
+    ("application/x-javascript", "js"),
+    ("application/ecmascript", "js"),
+    ("application/yaml", "yaml"),
+    ("application/x-yaml", "yaml"),
+    ("text/yaml", "yaml"),
+    ("text/x-yaml", "yaml"),
+    ("text/markdown", "md"),
+    ("text/x-markdown", "md"),
+    ("text/csv", "csv"),
+    ("text/tab-separated-values", "tsv"),
+    ("application/graphql", "graphql"),
+    ("application/sql", "sql"),
+    ("text/x-python", "py"),
+    ("application/x-sh", "sh"),
+    ("text/x-shellscript", "sh"),
+    ("text/x-diff", "diff"),
+    ("text/x-patch", "diff"),
+    ("text/x-rust", "rs"),
+    ("text/x-java-source", "java"),
+    ("application/x-httpd-php", "php"),
+];
+
+/// the syntect extension to highlight a mime type with.
+pub fn syntax_extension(m: &Mime) -> Option<&'static str> {
+    let essence = m.essence_str();
+    if let Some((_, extension)) = SYNTAXES.iter().find(|(e, _)| *e == essence) {
+        return Some(extension);
+    }
+    match m.suffix()?.as_str() {
+        "json" => Some("json"),
+        "xml" => Some("xml"),
+        "yaml" => Some("yaml"),
+        _ => None,
+    }
+}
+
+/// `EXTRA_SYNTAXES` in a set of their own, rebuilding the defaults with them is slow.
+fn extra_syntax_set() -> SyntaxSet {
+    let mut builder = SyntaxSetBuilder::new();
+    for source in EXTRA_SYNTAXES {
+        let syntax = SyntaxDefinition::load_from_str(source, true, None)
+            .expect("bundled syntaxes are valid");
+        builder.add(syntax);
+    }
+    builder.build()
+}
+
+/// the syntect defaults, loaded from their prebuilt dump, and the extra syntaxes.
+fn syntax_sets() -> &'static (SyntaxSet, SyntaxSet) {
+    static SETS: OnceLock<(SyntaxSet, SyntaxSet)> = OnceLock::new();
+    SETS.get_or_init(|| (SyntaxSet::load_defaults_newlines(), extra_syntax_set()))
+}
+
+/// add every `.tmTheme` in `dir` to `themes`, a broken one is skipped with a warning.
+fn add_themes(themes: &mut ThemeSet, dir: &Path) {
+    let Ok(entries) = fs::read_dir(dir) else {
+        return;
+    };
+    for path in entries.filter_map(|e| Some(e.ok()?.path())) {
+        if path.extension().and_then(|e| e.to_str()) != Some("tmTheme") {
+            continue;
+        }
+        let Some(name) = path.file_stem().and_then(|s| s.to_str()) else {
+            continue;
+        };
+        match ThemeSet::get_theme(&path) {
+            Ok(theme) => {
+                themes.themes.insert(name.to_string(), theme);
+            }
+            Err(e) => eprintln!("Skipping theme {}: {}", path.display(), e),
+        }
+    }
+}
+
+/// the built-in themes plus the ones in the `themes` config directory.
+pub fn theme_set() -> &'static ThemeSet {
+    static THEMES: OnceLock<ThemeSet> = OnceLock::new();
+    THEMES.get_or_init(|| {
+        let mut themes = ThemeSet::load_defaults();
+        if let Some(dir) = config_dir() {
+            add_themes(&mut themes, &dir.join("themes"));
+        }
+        themes
+    })
+}
+
+/// the theme for `--style`.
+pub fn theme(style: &str) -> Result<&'static Theme> {
+    theme_set()
+        .themes
+        .get(style)
+        .ok_or_else(|| anyhow!("Unknown style {}, see --list-styles", style))
+}
+
+/// print the available styles, one per line.
+pub fn list_styles() {
+    for name in theme_set().themes.keys() {
+        match name.as_str() {
+            DEFAULT_STYLE => println!("{} (default)", name),
+            _ => println!("{}", name),
+        }
+    }
+}
+
+/// highlights text a line at a time, lines come out plain when there is no syntax for
+/// the extension or highlighting fails part way.
+pub struct Highlighter<'a> {
+    lines: Option<(HighlightLines<'a>, &'static SyntaxSet)>,
+}
+
+impl<'a> Highlighter<'a> {
+    /// `None` prints every line plain.
+    pub fn new(extension: Option<&str>, theme: &'a Theme) -> Self {
+        let (defaults, extras) = syntax_sets();
+        let lines = extension.and_then(|extension| {
+            let (ps, syntax) = match defaults.find_syntax_by_extension(extension) {
+                Some(syntax) => (defaults, syntax),
+                None => (extras, extras.find_syntax_by_extension(extension)?),
+            };
+            Some((HighlightLines::new(syntax, theme), ps))
+        });
+        Self { lines }
+    }
+
+    /// the escaped line, `line` should keep its line ending.
+    pub fn highlight_line(&mut self, line: &str) -> String {
+        if let Some((h, ps)) = &mut self.lines {
+            match h.highlight_line(line, ps) {
+                Ok(ranges) => return as_24_bit_terminal_escaped(&ranges[..], false),
+                // the parse state is lost, the rest is printed as it is
+                Err(_) => self.lines = None,
+            }
+        }
+        line.to_string()
+    }
+}
+
+pub fn highlighting_text(text: &str, extension: &str, theme: &Theme) {
+    let mut h = Highlighter::new(Some(extension), theme);
+    for line in LinesWithEndings::from(text) {
+        print!("{}", h.highlight_line(line));
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::testing;
+
+    #[test]
+    fn test_syntax_extension() {
+        let ext = |s: &str| syntax_extension(&s.parse().unwrap());
+        assert_eq!(ext("application/json; charset=utf-8"), Some("json"));
+        assert_eq!(ext("application/problem+json"), Some("json"));
+        assert_eq!(ext("application/vnd.api+json"), Some("json"));
+        assert_eq!(ext("application/atom+xml"), Some("xml"));
+        assert_eq!(ext("text/xml"), Some("xml"));
+        assert_eq!(ext("text/html; charset=gbk"), Some("html"));
+        assert_eq!(ext("application/openapi+yaml"), Some("yaml"));
+        assert_eq!(ext("text/csv"), Some("csv"));
+        assert_eq!(ext("application/graphql"), Some("graphql"));
+        assert_eq!(ext("text/plain"), None);
+    }
+
+    #[test]
+    fn test_every_extension_has_a_syntax() {
+        let (defaults, extras) = syntax_sets();
+        for (essence, extension) in SYNTAXES {
+            assert!(
+                defaults.find_syntax_by_extension(extension).is_some()
+                    || extras.find_syntax_by_extension(extension).is_some(),
+                "no syntax for {}",
+                essence
+            );
+        }
+    }
+
+    #[test]
+    fn test_add_themes() {
+        let dir = testing::temp_path("themes");
+        fs::create_dir_all(&dir).unwrap();
+        fs::write(
+            dir.join("Mine.tmTheme"),
+            r#"<?xml version="1.0" encoding="UTF-8"?>
+<plist version="1.0"><dict>
+<key>name</key><string>Mine</string>
+<key>settings</key><array><dict><key>settings</key><dict>
+<key>foreground</key><string>#112233</string>
+</dict></dict></array>
+</dict></plist>"#,
+        )
+        .unwrap();
+        fs::write(dir.join("Broken.tmTheme"), "not a plist").unwrap();
+        fs::write(dir.join("notes.txt"), "").unwrap();
+
+        let mut themes = ThemeSet::new();
+        add_themes(&mut themes, &dir);
+        fs::remove_dir_all(&dir).unwrap();
+        assert_eq!(themes.themes.keys().collect::<Vec<_>>(), ["Mine"]);
+    }
+
+    #[test]
+    fn test_highlighter() {
+        let theme = theme(DEFAULT_STYLE).unwrap();
+        let mut plain = Highlighter::new(Some("no-such-syntax"), theme);
+        assert_eq!(plain.highlight_line("{\"a\": 1}\n"), "{\"a\": 1}\n");
+        let mut none = Highlighter::new(None, theme);
+        assert_eq!(none.highlight_line("x\n"), "x\n");
+        let mut json = Highlighter::new(Some("json"), theme);
+        assert!(json.highlight_line("{\"a\": 1}\n").contains("\x1b[38;2;"));
+    }
+
+    #[test]
+    fn test_theme() {
+        assert!(theme(DEFAULT_STYLE).is_ok());
+        assert!(theme("base16-ocean.dark").is_ok());
+        assert!(theme("no-such-style").is_err());
+    }
+}
@@ -0,0 +1,416 @@
+use std::{collections::HashSet, fs, path::Path, str::FromStr};
+
+use anyhow::{anyhow, Context, Result};
+use reqwest::header::{self, HeaderMap, HeaderName, HeaderValue};
+use reqwest::multipart::{Form, Part};
+use serde_json::{Map, Value};
+
+use crate::cookies;
+use crate::nested;
+
+/// how the key and value of a request item are tied together.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Separator {
+    /// `Header:Value`, an empty value removes the header.
+    Header,
+    /// `Header;`, sends the header with an empty value.
+    EmptyHeader,
+    /// `param==value`, appended to the query string.
+    Query,
+    /// `field=value`, a string field of the body.
+    Data,
+    /// `field:=json`, a raw JSON field of the body.
+    Json,
+    /// `field=@file`, a string field read from a file.
+    DataFile,
+    /// `field:=@file.json`, a raw JSON field read from a file.
+    JsonFile,
+    /// `field@path;type=mime;filename=x`, a file upload for multipart forms.
+    File,
+}
+
+/// separators sorted so that the longest one wins at the same position.
+const SEPARATORS: &[(&str, Separator)] = &[
+    (":=@", Separator::JsonFile),
+    ("=@", Separator::DataFile),
+    ("==", Separator::Query),
+    (":=", Separator::Json),
+    ("=", Separator::Data),
+    (":", Separator::Header),
+    ("@", Separator::File),
+    (";", Separator::EmptyHeader),
+];
+
+/// characters which can be escaped with a backslash in the key.
+const ESCAPABLE: &[char] = &['\\', '=', ':', '@', ';'];
+
+/// a request item from the command line, split on its first unescaped separator.
+#[derive(Debug, PartialEq)]
+pub struct KvPair {
+    pub k: String,
+    pub sep: Separator,
+    pub v: String,
+}
+
+impl FromStr for KvPair {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let err = || anyhow!("Failed to parse {}", s);
+        let mut k = String::new();
+        let mut rest = s;
+        while let Some(c) = rest.chars().next() {
+            if c == '\\' {
+                if let Some(escaped) = rest[1..].chars().next().filter(|c| ESCAPABLE.contains(c)) {
+                    k.push(escaped);
+                    rest = &rest[1 + escaped.len_utf8()..];
+                    continue;
+                }
+            }
+            if let Some((token, sep)) = SEPARATORS.iter().find(|(t, _)| rest.starts_with(t)) {
+                let v = &rest[token.len()..];
+                // a bare `@file` is the only item without a key, it sends the file as the body
+                let keyless = k.is_empty() && *sep != Separator::File;
+                if keyless || (*sep == Separator::EmptyHeader && !v.is_empty()) {
+                    return Err(err());
+                }
+                return Ok(Self {
+                    k,
+                    sep: *sep,
+                    v: v.into(),
+                });
+            }
+            k.push(c);
+            rest = &rest[c.len_utf8()..];
+        }
+        Err(err())
+    }
+}
+
+pub fn parse_kv_pair(s: &str) -> Result<KvPair> {
+    s.parse()
+}
+
+/// a file to upload, parsed from `path;type=mime;filename=x`.
+#[derive(Debug, PartialEq)]
+pub struct FileField {
+    pub name: String,
+    pub path: String,
+    pub mime: Option<String>,
+    pub filename: Option<String>,
+}
+
+impl FileField {
+    fn new(name: &str, value: &str) -> Self {
+        let mut field = Self {
+            name: name.into(),
+            path: value.into(),
+            mime: None,
+            filename: None,
+        };
+        // parameters are peeled off the end so the path itself may contain `;`
+        while let Some((head, param)) = field.path.rsplit_once(';') {
+            if let Some(mime) = param.strip_prefix("type=") {
+                field.mime = Some(mime.into());
+            } else if let Some(filename) = param.strip_prefix("filename=") {
+                field.filename = Some(filename.into());
+            } else {
+                break;
+            }
+            field.path = head.into();
+        }
+        field
+    }
+
+    fn part(&self) -> Result<Part> {
+        let path = Path::new(&self.path);
+        let content = fs::read(path).with_context(|| format!("Failed to read {}", self.path))?;
+        let filename = match &self.filename {
+            Some(filename) => filename.clone(),
+            None => path
+                .file_name()
+                .map(|name| name.to_string_lossy().into_owned())
+                .unwrap_or_default(),
+        };
+        let mime = match &self.mime {
+            Some(mime) => mime.clone(),
+            None => mime_guess::from_path(path)
+                .first_or_octet_stream()
+                .to_string(),
+        };
+        Ok(Part::bytes(content).file_name(filename).mime_str(&mime)?)
+    }
+}
+
+/// request items sorted by where they end up in the request.
+#[derive(Debug, Default)]
+pub struct RequestItems {
+    /// headers to send, `None` removes the header.
+    pub headers: Vec<(HeaderName, Option<HeaderValue>)>,
+    pub query: Vec<(String, String)>,
+    pub data: Vec<(String, Value)>,
+    pub files: Vec<FileField>,
+    /// the path of a bare `@file` item, sent as the raw body.
+    pub body_file: Option<String>,
+}
+
+impl RequestItems {
+    /// interpret the pairs, reading any referenced files.
+    pub fn from_pairs(pairs: &[KvPair]) -> Result<Self> {
+        let mut items = Self::default();
+        for pair in pairs {
+            match pair.sep {
+                Separator::Header | Separator::EmptyHeader => {
+                    let name: HeaderName = pair.k.parse()?;
+                    let value = match pair.sep {
+                        Separator::Header if pair.v.is_empty() => None,
+                        _ => Some(pair.v.parse()?),
+                    };
+                    items.headers.push((name, value));
+                }
+                Separator::Query => items.query.push((pair.k.clone(), pair.v.clone())),
+                Separator::Data => items
+                    .data
+                    .push((pair.k.clone(), Value::String(pair.v.clone()))),
+                Separator::Json => {
+                    let value = serde_json::from_str(&pair.v)
+                        .with_context(|| format!("Invalid JSON in {}:={}", pair.k, pair.v))?;
+                    items.data.push((pair.k.clone(), value));
+                }
+                Separator::DataFile => {
+                    let text = read_file(&pair.v)?;
+                    items.data.push((pair.k.clone(), Value::String(text)));
+                }
+                Separator::JsonFile => {
+                    let text = read_file(&pair.v)?;
+                    let value = serde_json::from_str(&text)
+                        .with_context(|| format!("Invalid JSON in {}", pair.v))?;
+                    items.data.push((pair.k.clone(), value));
+                }
+                Separator::File if pair.k.is_empty() => {
+                    if items.body_file.is_some() {
+                        return Err(anyhow!("Only one @file body can be sent"));
+                    }
+                    items.body_file = Some(pair.v.clone());
+                }
+                Separator::File => items.files.push(FileField::new(&pair.k, &pair.v)),
+            }
+        }
+        Ok(items)
+    }
+
+    /// apply the header items on top of `headers`, repeated items are all sent. `Cookie`
+    /// items are merged into the cookies already there, e.g. those of a session.
+    pub fn apply_headers(&self, headers: &mut HeaderMap) -> Result<()> {
+        let mut seen = HashSet::new();
+        for (name, value) in self.headers.iter() {
+            if let (&header::COOKIE, Some(value)) = (name, value) {
+                cookies::merge_header(headers, &String::from_utf8_lossy(value.as_bytes()))?;
+                continue;
+            }
+            if seen.insert(name) || value.is_none() {
+                headers.remove(name);
+            }
+            if let Some(value) = value {
+                headers.append(name, value.clone());
+            }
+        }
+        Ok(())
+    }
+
+    /// the data items as a JSON object, bracketed keys like `user[name]` build
+    /// nested objects and arrays.
+    pub fn json_body(&self) -> Result<Value> {
+        let mut body = Map::new();
+        for (k, v) in self.data.iter() {
+            nested::insert(&mut body, k, v.clone())?;
+        }
+        Ok(Value::Object(body))
+    }
+
+    /// the data items as url encoded form fields, keys are kept as written.
+    pub fn form_body(&self) -> Result<Vec<(String, String)>> {
+        self.data
+            .iter()
+            .map(|(k, v)| match v {
+                Value::String(s) => Ok((k.clone(), s.clone())),
+                _ => Err(anyhow!("Form field {} must be text, not raw JSON", k)),
+            })
+            .collect()
+    }
+
+    /// the data and file items as a multipart form, reading the files.
+    pub fn multipart_body(&self) -> Result<Form> {
+        let mut form = Form::new();
+        for (k, v) in self.form_body()? {
+            form = form.text(k, v);
+        }
+        for file in self.files.iter() {
+            form = form.part(file.name.clone(), file.part()?);
+        }
+        Ok(form)
+    }
+}
+
+fn read_file(path: &str) -> Result<String> {
+    fs::read_to_string(path).with_context(|| format!("Failed to read {}", path))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn pair(k: &str, sep: Separator, v: &str) -> KvPair {
+        KvPair {
+            k: k.into(),
+            sep,
+            v: v.into(),
+        }
+    }
+
+    #[test]
+    fn test_parse_kv_pair() {
+        assert!(parse_kv_pair("a").is_err());
+
+        assert_eq!(
+            parse_kv_pair("name=bob").unwrap(),
+            pair("name", Separator::Data, "bob")
+        );
+
+        assert_eq!(
+            parse_kv_pair("age=").unwrap(),
+            pair("age", Separator::Data, "")
+        );
+
+        assert_eq!(
+            parse_kv_pair("token=a=b=").unwrap(),
+            pair("token", Separator::Data, "a=b=")
+        );
+    }
+
+    #[test]
+    fn test_parse_separators() {
+        assert_eq!(
+            parse_kv_pair("Host:example.com:8080").unwrap(),
+            pair("Host", Separator::Header, "example.com:8080")
+        );
+        assert_eq!(
+            parse_kv_pair("X-Empty;").unwrap(),
+            pair("X-Empty", Separator::EmptyHeader, "")
+        );
+        assert_eq!(
+            parse_kv_pair("q==rust").unwrap(),
+            pair("q", Separator::Query, "rust")
+        );
+        assert_eq!(
+            parse_kv_pair("age:=18").unwrap(),
+            pair("age", Separator::Json, "18")
+        );
+        assert_eq!(
+            parse_kv_pair("bio=@bio.txt").unwrap(),
+            pair("bio", Separator::DataFile, "bio.txt")
+        );
+        assert_eq!(
+            parse_kv_pair("meta:=@meta.json").unwrap(),
+            pair("meta", Separator::JsonFile, "meta.json")
+        );
+        assert_eq!(
+            parse_kv_pair("avatar@a.png;type=image/png").unwrap(),
+            pair("avatar", Separator::File, "a.png;type=image/png")
+        );
+        assert_eq!(
+            parse_kv_pair("@payload.xml").unwrap(),
+            pair("", Separator::File, "payload.xml")
+        );
+        assert!(parse_kv_pair("=value").is_err());
+        assert!(parse_kv_pair("a;b").is_err());
+    }
+
+    #[test]
+    fn test_parse_escaped_separators() {
+        assert_eq!(
+            parse_kv_pair(r"a\:b=c").unwrap(),
+            pair("a:b", Separator::Data, "c")
+        );
+        assert_eq!(
+            parse_kv_pair(r"a\=\=b==c").unwrap(),
+            pair("a==b", Separator::Query, "c")
+        );
+        assert_eq!(
+            parse_kv_pair(r"path\\=C:\dir").unwrap(),
+            pair(r"path\", Separator::Data, r"C:\dir")
+        );
+    }
+
+    #[test]
+    fn test_request_items() {
+        let pairs = [
+            pair("X-Token", Separator::Header, "abc"),
+            pair("X-Gone", Separator::Header, ""),
+            pair("page", Separator::Query, "2"),
+            pair("name", Separator::Data, "bob"),
+            pair("tags", Separator::Json, r#"["a", 1]"#),
+        ];
+        let items = RequestItems::from_pairs(&pairs).unwrap();
+
+        let mut headers = HeaderMap::new();
+        headers.insert("x-gone", "1".parse().unwrap());
+        items.apply_headers(&mut headers).unwrap();
+        assert_eq!(headers.get("x-token").unwrap(), "abc");
+        assert!(headers.get("x-gone").is_none());
+
+        assert_eq!(items.query, vec![("page".to_string(), "2".to_string())]);
+        assert_eq!(
+            items.json_body().unwrap(),
+            serde_json::json!({"name": "bob", "tags": ["a", 1]})
+        );
+
+        assert!(RequestItems::from_pairs(&[pair("n", Separator::Json, "{")]).is_err());
+    }
+
+    #[test]
+    fn test_file_field() {
+        assert_eq!(
+            FileField::new("doc", "a;b.txt;type=text/plain;filename=c.txt"),
+            FileField {
+                name: "doc".into(),
+                path: "a;b.txt".into(),
+                mime: Some("text/plain".into()),
+                filename: Some("c.txt".into()),
+            }
+        );
+        assert_eq!(FileField::new("doc", "x.bin").path, "x.bin");
+    }
+
+    #[test]
+    fn test_form_body() {
+        let items = RequestItems::from_pairs(&[
+            pair("user[name]", Separator::Data, "bob"),
+            pair("age", Separator::Data, "18"),
+        ])
+        .unwrap();
+        assert_eq!(
+            items.form_body().unwrap(),
+            vec![
+                ("user[name]".to_string(), "bob".to_string()),
+                ("age".to_string(), "18".to_string())
+            ]
+        );
+
+        let items = RequestItems::from_pairs(&[pair("age", Separator::Json, "18")]).unwrap();
+        assert!(items.form_body().is_err());
+    }
+
+    #[test]
+    fn test_body_file() {
+        let items = RequestItems::from_pairs(&[pair("", Separator::File, "a.xml")]).unwrap();
+        assert_eq!(items.body_file.as_deref(), Some("a.xml"));
+        assert!(items.files.is_empty());
+
+        let twice = [
+            pair("", Separator::File, "a.xml"),
+            pair("", Separator::File, "b.xml"),
+        ];
+        assert!(RequestItems::from_pairs(&twice).is_err());
+    }
+}
@@ -1,74 +1,160 @@
-use std::{collections::HashMap, str::FromStr};
+use std::fs;
+use std::io::{self, IsTerminal, Read};
+use std::process::ExitCode;
 
-use anyhow::anyhow;
-use anyhow::Result;
-use colored::*;
+use anyhow::{anyhow, Context, Result};
 
 use clap::Parser;
 use mime::Mime;
-use reqwest::{self, header, Client, Response, Url};
-
-use syntect::easy::HighlightLines;
-use syntect::highlighting::{Style, ThemeSet};
-use syntect::parsing::SyntaxSet;
-use syntect::util::{as_24_bit_terminal_escaped, LinesWithEndings};
+use reqwest::{self, header, Client, Method, RequestBuilder, StatusCode, Url};
+
+mod auth;
+mod config;
+mod cookies;
+mod download;
+mod exit;
+mod format;
+mod highlight;
+mod items;
+mod nested;
+mod output;
+mod redirect;
+mod retry;
+mod session;
+#[cfg(test)]
+mod testing;
+mod tls;
+mod util;
+
+use auth::AuthOpts;
+use config::Config;
+use cookies::CookieOpts;
+use download::DownloadOpts;
+use exit::Exit;
+use items::{parse_kv_pair, KvPair, RequestItems};
+use output::OutputOpts;
+use redirect::{RedirectOpts, Sent};
+use retry::RetryOpts;
+use session::SessionOpts;
+use tls::TlsOpts;
 
 /// minihttpie
 #[derive(Parser, Debug)]
 #[clap(version = "1.0", author = "ncp-z@npc-z.com")]
 #[clap(propagate_version = true)]
+#[clap(arg_required_else_help = true, args_conflicts_with_subcommands = true)]
 struct Opts {
+    /// list the highlighting themes for --style, custom .tmTheme files are read from
+    /// the themes directory next to config.json
+    #[clap(long)]
+    list_styles: bool,
     #[clap(subcommand)]
-    subcmd: SubCommand,
+    subcmd: Option<SubCommand>,
 }
 
-/// sub-commands, support get / post now.
+/// sub-commands, one per HTTP method, plus `request` for custom methods.
 #[derive(Parser, Debug)]
 enum SubCommand {
-    Get(Get),
-    Post(Post),
+    /// send a GET request.
+    Get(RequestArgs),
+    /// send a POST request.
+    Post(RequestArgs),
+    /// send a PUT request.
+    Put(RequestArgs),
+    /// send a PATCH request.
+    Patch(RequestArgs),
+    /// send a DELETE request.
+    Delete(RequestArgs),
+    /// send a HEAD request, only status and headers are printed.
+    Head(RequestArgs),
+    /// send an OPTIONS request.
+    Options(RequestArgs),
+    /// send a request with any other method, e.g. PURGE or PROPFIND.
+    Request(Custom),
 }
 
-/// feed get with an url.
-#[derive(Parser, Debug)]
-struct Get {
-    /// get url
-    #[clap(parse(try_from_str = parse_url))]
-    url: String,
+impl SubCommand {
+    fn method(&self) -> Method {
+        match self {
+            SubCommand::Get(_) => Method::GET,
+            SubCommand::Post(_) => Method::POST,
+            SubCommand::Put(_) => Method::PUT,
+            SubCommand::Patch(_) => Method::PATCH,
+            SubCommand::Delete(_) => Method::DELETE,
+            SubCommand::Head(_) => Method::HEAD,
+            SubCommand::Options(_) => Method::OPTIONS,
+            SubCommand::Request(custom) => custom.method.clone(),
+        }
+    }
+
+    fn args(&self) -> &RequestArgs {
+        match self {
+            SubCommand::Get(args)
+            | SubCommand::Post(args)
+            | SubCommand::Put(args)
+            | SubCommand::Patch(args)
+            | SubCommand::Delete(args)
+            | SubCommand::Head(args)
+            | SubCommand::Options(args) => args,
+            SubCommand::Request(custom) => &custom.args,
+        }
+    }
 }
 
-/// feed post with an url and optional key=value pairs as body.
+/// feed a method with an url and optional request items.
 #[derive(Parser, Debug)]
-struct Post {
-    /// post url
+struct RequestArgs {
+    /// request url
     #[clap(parse(try_from_str = parse_url))]
     url: String,
-    /// post body
+    /// request items: Header:Value, Header: (removes it), Header;, param==value,
+    /// field=value, field:=json, field=@file, field:=@file.json, user[name]=value,
+    /// field@path;type=mime;filename=x (upload with --form or --multipart) and
+    /// @file (raw body)
     #[clap(parse(try_from_str = parse_kv_pair))]
-    body: Vec<KvPair>,
+    items: Vec<KvPair>,
+    /// send the text as the raw request body
+    #[clap(long)]
+    raw: Option<String>,
+    /// don't read the request body from stdin when it is not a terminal
+    #[clap(long)]
+    ignore_stdin: bool,
+    /// send data items as application/x-www-form-urlencoded, or as
+    /// multipart/form-data when there are file uploads
+    #[clap(short, long)]
+    form: bool,
+    /// send data items and file uploads as multipart/form-data
+    #[clap(long)]
+    multipart: bool,
+    /// exit with 3, 4 or 5 on 3xx, 4xx or 5xx responses
+    #[clap(long)]
+    check_status: bool,
+    #[clap(flatten)]
+    auth: AuthOpts,
+    #[clap(flatten)]
+    session: SessionOpts,
+    #[clap(flatten)]
+    cookies: CookieOpts,
+    #[clap(flatten)]
+    redirect: RedirectOpts,
+    #[clap(flatten)]
+    retry: RetryOpts,
+    #[clap(flatten)]
+    tls: TlsOpts,
+    #[clap(flatten)]
+    output: OutputOpts,
+    #[clap(flatten)]
+    download: DownloadOpts,
 }
 
-#[derive(Debug, PartialEq)]
-struct KvPair {
-    k: String,
-    v: String,
-}
-
-impl FromStr for KvPair {
-    type Err = anyhow::Error;
-
-    fn from_str(s: &str) -> Result<Self, Self::Err> {
-        let mut split = s.split("=");
-        let err = || anyhow!(format!("Failed to parse {}", s));
-        Ok(Self {
-            k: (split.next().ok_or_else(err)?).to_string(),
-            v: (split.next().ok_or_else(err)?).to_string(),
-        })
-    }
-}
-
-fn parse_kv_pair(s: &str) -> Result<KvPair> {
-    Ok(s.parse()?)
+/// feed a custom method before the url and body.
+#[derive(Parser, Debug)]
+struct Custom {
+    /// request method, e.g. PURGE
+    #[clap(parse(try_from_str = parse_method))]
+    method: Method,
+    #[clap(flatten)]
+    args: RequestArgs,
 }
 
 fn parse_url(url: &str) -> Result<String> {
@@ -76,101 +162,402 @@ fn parse_url(url: &str) -> Result<String> {
     Ok(url.into())
 }
 
-async fn get(client: Client, args: &Get) -> Result<()> {
-    let resp = client.get(&args.url).send().await?;
-    Ok(print_resp(resp).await?)
+fn parse_method(s: &str) -> Result<Method> {
+    Ok(Method::from_bytes(s.to_uppercase().as_bytes())?)
 }
 
-async fn post(client: Client, args: &Post) -> Result<()> {
-    let mut body = HashMap::new();
-    for pair in args.body.iter() {
-        body.insert(&pair.k, &pair.v);
+/// send the request and print the response, returning its status.
+async fn send(
+    client: Client,
+    config: &Config,
+    method: Method,
+    args: &RequestArgs,
+) -> Result<StatusCode> {
+    let printer = args.output.printer(&method)?;
+    colored::control::set_override(printer.pretty.colors());
+    let items = RequestItems::from_pairs(&args.items)?;
+    let url: Url = args.url.parse()?;
+    let mut session = args.session.load(&url)?;
+    let mut jar = args.cookies.jar()?;
+    let saved_auth = session.as_ref().and_then(|s| s.auth.as_ref());
+    let auth = args.auth.or_saved(saved_auth);
+    let auth = auth.plugin(&client, url.host_str()).await?;
+    // defaults are sent per request rather than by the client so that items can remove them
+    let mut headers = config.default_headers()?;
+    if let Some(session) = &session {
+        session.apply(&mut headers, &url)?;
+    }
+    args.cookies.apply(jar.as_ref(), &mut headers, &url)?;
+    items.apply_headers(&mut headers)?;
+    let resume_from = args.download.resume_from();
+    if let Some(offset) = resume_from {
+        headers.insert(header::RANGE, format!("bytes={}-", offset).parse()?);
     }
-    let resp = client.post(&args.url).json(&body).send().await?;
-    Ok(print_resp(resp).await?)
-}
-
-fn print_status(resp: &Response) {
-    let status = format!("{:?} {}", resp.version(), resp.status()).blue();
-    println!("{}\n", status);
-}
 
-fn print_headers(resp: &Response) {
-    for (name, value) in resp.headers() {
-        println!("{}: {:?}", name.to_string().green(), value);
+    let build = || -> Result<_> {
+        let req = client
+            .request(method.clone(), &args.url)
+            .query(&items.query);
+        let mut req = set_body(req, args, &items)?
+            .headers(headers.clone())
+            .build()?;
+        if let Some(auth) = &auth {
+            auth.apply(&mut req)?;
+        }
+        Ok(req)
+    };
+    let req = build()?;
+    printer.print_request(&req)?;
+    // kept to answer an auth challenge or follow a redirect with
+    let mut sent = Sent::new(&req);
+    // retries are logged along with the requests
+    let log = printer.print.request_headers;
+    let mut resp = args.retry.execute(&client, req, log).await?;
+    if let (Some(auth), StatusCode::UNAUTHORIZED) = (&auth, resp.status()) {
+        // streamed bodies can't be sent twice, they are built again from the items
+        let mut retry = match sent.try_clone() {
+            Some(retry) => retry,
+            None => build()?,
+        };
+        if auth.respond(&mut retry, &resp)? {
+            printer.print_request(&retry)?;
+            sent = Sent::new(&retry);
+            resp = args.retry.execute(&client, retry, log).await?;
+        }
+    }
+    let mut redirects = 0;
+    // cookies set along a redirect chain, sent on even without a session or jar
+    let mut chain_cookies = Vec::new();
+    loop {
+        cookies::store_all(&mut chain_cookies, resp.url(), resp.headers());
+        if let Some(session) = &mut session {
+            session.store_cookies(resp.url(), resp.headers());
+        }
+        if let Some(jar) = &mut jar {
+            cookies::store_all(&mut jar.cookies, resp.url(), resp.headers());
+        }
+        let next = match args.redirect.follow() {
+            true => redirect::next(&sent, resp.url(), resp.status(), resp.headers())?,
+            false => None,
+        };
+        let mut next = match next {
+            Some(next) => next,
+            None => break,
+        };
+        redirects += 1;
+        args.redirect.check(redirects)?;
+        // stored cookies go wherever their domain matches, while credentials and --cookie
+        // values are only for the host of the original url
+        let next_url = next.url().clone();
+        let stored = [
+            session.as_ref().map(|s| &s.cookies[..]),
+            jar.as_ref().map(|j| &j.cookies[..]),
+            Some(&chain_cookies[..]),
+        ];
+        for stored in stored.into_iter().flatten() {
+            if let Some(cookie) = cookies::header(stored, &next_url, util::now()) {
+                cookies::merge_header(next.headers_mut(), &cookie)?;
+            }
+        }
+        if redirect::same_origin(&url, &next_url) {
+            args.cookies.apply(None, next.headers_mut(), &next_url)?;
+            if let Some(auth) = &auth {
+                auth.apply(&mut next)?;
+            }
+        }
+        if args.redirect.all() {
+            printer.print_resp(resp).await?;
+            println!();
+            printer.print_request(&next)?;
+        }
+        sent = Sent::new(&next);
+        resp = args.retry.execute(&client, next, log).await?;
+    }
+    if let Some(session) = &mut session {
+        session.remember(&url, &items.headers, args.auth.saved());
+        session.save()?;
+    }
+    if let Some(jar) = &jar {
+        jar.save()?;
+    }
+    let status = resp.status();
+    if download::complete(status, resp.headers(), resume_from) {
+        printer.print_resp_headers(&resp);
+        download::report_complete(&args.download, resume_from.unwrap_or_default());
+        // nothing was left to download, which is no error for --check-status
+        return Ok(StatusCode::OK);
     }
+    // error pages are printed as usual instead of being saved
+    if args.download.enabled() && status.is_success() {
+        printer.print_resp_headers(&resp);
+        download::save(resp, &args.download, resume_from).await?;
+    } else {
+        printer.print_resp(resp).await?;
+    }
+    Ok(status)
 }
 
-fn highlighting_text(text: &str, extension: &str) {
-    // Load these once at the start of your program
-    let ps = SyntaxSet::load_defaults_newlines();
-    let ts = ThemeSet::load_defaults();
-
-    let syntax = ps.find_syntax_by_extension(extension).unwrap();
-
-    let mut h = HighlightLines::new(syntax, &ts.themes["base16-ocean.dark"]);
-
-    for line in LinesWithEndings::from(text) {
-        let ranges: Vec<(Style, &str)> = h.highlight_line(line, &ps).unwrap();
-        let escaped = as_24_bit_terminal_escaped(&ranges[..], true);
-        print!("{}", escaped);
+/// set the raw body, or encode the data and file items as JSON, a form or a
+/// multipart form. a Content-Type header item overrides the guessed type.
+fn set_body(
+    req: RequestBuilder,
+    args: &RequestArgs,
+    items: &RequestItems,
+) -> Result<RequestBuilder> {
+    if let Some((body, mime)) = raw_body(args, items)? {
+        if !items.data.is_empty() || !items.files.is_empty() {
+            return Err(anyhow!("A raw body can't be mixed with data items"));
+        }
+        return Ok(req.header(header::CONTENT_TYPE, mime.as_ref()).body(body));
     }
+    if args.multipart || (args.form && !items.files.is_empty()) {
+        return Ok(req.multipart(items.multipart_body()?));
+    }
+    if !items.files.is_empty() {
+        return Err(anyhow!("File uploads need --form or --multipart"));
+    }
+    if items.data.is_empty() {
+        return Ok(req);
+    }
+    if args.form {
+        return Ok(req.form(&items.form_body()?));
+    }
+    Ok(req.json(&items.json_body()?))
 }
 
-fn print_body(m: Option<Mime>, body: &String) {
-    match m {
-        Some(v) if v == mime::APPLICATION_JSON => {
-            // println!("{}", jsonxf::pretty_print(body).unwrap().cyan());
-            highlighting_text(body, "json");
+/// the body from --raw, a bare @file item or piped stdin, with its default mime.
+fn raw_body(args: &RequestArgs, items: &RequestItems) -> Result<Option<(Vec<u8>, Mime)>> {
+    let default_mime = if args.form {
+        mime::APPLICATION_WWW_FORM_URLENCODED
+    } else {
+        mime::APPLICATION_JSON
+    };
+    match (&args.raw, &items.body_file) {
+        (Some(_), Some(_)) => Err(anyhow!("Use either --raw or @file, not both")),
+        (Some(raw), None) => Ok(Some((raw.clone().into_bytes(), default_mime))),
+        (None, Some(path)) => {
+            let body = fs::read(path).with_context(|| format!("Failed to read {}", path))?;
+            let mime = mime_guess::from_path(path).first().unwrap_or(default_mime);
+            Ok(Some((body, mime)))
         }
-        Some(v) if v == mime::TEXT_HTML => {
-            highlighting_text(body, "html");
+        (None, None) => {
+            // stdin is only read when nothing else makes up the body
+            let has_items = !items.data.is_empty() || !items.files.is_empty();
+            let stdin = io::stdin();
+            if args.ignore_stdin || has_items || stdin.is_terminal() {
+                return Ok(None);
+            }
+            let mut body = Vec::new();
+            stdin.lock().read_to_end(&mut body)?;
+            Ok((!body.is_empty()).then_some((body, default_mime)))
         }
-        _ => println!("{}", body),
     }
 }
 
-async fn print_resp(resp: Response) -> Result<()> {
-    print_status(&resp);
-    print_headers(&resp);
-
-    let mime = get_content_type(&resp);
-    let body = resp.text().await?;
-    println!();
-    print_body(mime, &body);
-    Ok(())
-}
-
-fn get_content_type(resp: &Response) -> Option<Mime> {
-    resp.headers()
-        .get(header::CONTENT_TYPE)
-        .map(|v| v.to_str().unwrap().parse().unwrap())
+#[tokio::main]
+async fn main() -> ExitCode {
+    match run().await {
+        Ok(exit) => exit.map_or(ExitCode::SUCCESS, ExitCode::from),
+        Err(e) => {
+            eprintln!("Error: {:?}", e);
+            exit::for_error(&e).into()
+        }
+    }
 }
 
-#[tokio::main]
-async fn main() -> Result<()> {
+/// the exit code for --check-status, if the response asks for one.
+async fn run() -> Result<Option<Exit>> {
     let opts: Opts = Opts::parse();
     // dbg!(opts);
-
-    let mut headers = header::HeaderMap::new();
-    headers.insert("X-POWERED", "Rust".parse()?);
-    headers.insert(header::USER_AGENT, "Rust Httpie".parse()?);
-
-    let client = reqwest::Client::builder()
-        .default_headers(headers)
+    if opts.list_styles {
+        highlight::list_styles();
+        return Ok(None);
+    }
+    let subcmd = opts
+        .subcmd
+        .ok_or_else(|| anyhow!("Missing a sub-command"))?;
+
+    let config = Config::load()?;
+    // redirects are followed by `send` with --follow
+    let args = subcmd.args();
+    let client = args.retry.client(reqwest::Client::builder());
+    let client = args
+        .tls
+        .client(client)?
+        .redirect(reqwest::redirect::Policy::none())
         .build()?;
 
-    let result = match opts.subcmd {
-        SubCommand::Get(ref args) => get(client, args).await?,
-        SubCommand::Post(ref args) => post(client, args).await?,
-    };
-
-    Ok(result)
+    let status = send(client, &config, subcmd.method(), args).await?;
+    Ok(exit::for_status(status).filter(|_| args.check_status))
 }
 
 #[cfg(test)]
 mod tests {
     use super::*;
+    use std::sync::{Arc, Mutex};
+    use testing::Response;
+
+    #[test]
+    fn test_opts() {
+        use clap::CommandFactory;
+        Opts::command().debug_assert();
+    }
+
+    /// a server redirecting with `respond(path)` as status and Location, which records the
+    /// path, Authorization and Cookie of every request.
+    async fn stub<F>(respond: F, seen: Arc<Mutex<Vec<String>>>) -> String
+    where
+        F: Fn(&str) -> (u16, String) + Send + Sync + 'static,
+    {
+        testing::serve(move |req| {
+            let credentials: Vec<&str> = ["authorization", "cookie"]
+                .into_iter()
+                .filter_map(|name| req.header(name))
+                .collect();
+            seen.lock().unwrap().push(
+                format!("{} {}", req.path, credentials.join(" | "))
+                    .trim()
+                    .into(),
+            );
+            let (status, location) = respond(&req.path);
+            Response::new(status).header("Location", location)
+        })
+        .await
+    }
+
+    #[tokio::test]
+    async fn test_redirect_credentials() {
+        let seen_b = Arc::new(Mutex::new(Vec::new()));
+        let b = stub(
+            |path| match path {
+                "/start" => (302, "/next".into()),
+                _ => (200, "".into()),
+            },
+            seen_b.clone(),
+        )
+        .await;
+        let seen_a = Arc::new(Mutex::new(Vec::new()));
+        let a = stub(
+            move |path| match path {
+                "/to-b" => (302, format!("{}/start", b)),
+                _ => (200, "".into()),
+            },
+            seen_a.clone(),
+        )
+        .await;
+        let url = format!("{}/to-b", a);
+        let opts = Opts::parse_from([
+            "minihttpie",
+            "get",
+            &url,
+            "-a",
+            "alice:secret",
+            "--cookie",
+            "sid=topsecret",
+            "--follow",
+            "-q",
+            "--ignore-stdin",
+        ]);
+        let subcmd = opts.subcmd.unwrap();
+        let client = Client::builder()
+            .redirect(reqwest::redirect::Policy::none())
+            .build()
+            .unwrap();
+        send(client, &Config::default(), subcmd.method(), subcmd.args())
+            .await
+            .unwrap();
+
+        let seen_a = seen_a.lock().unwrap();
+        assert!(
+            seen_a[0].contains("Basic YWxpY2U6c2VjcmV0") && seen_a[0].contains("sid=topsecret")
+        );
+        // neither the first hop to b nor the one within b carries them
+        assert_eq!(*seen_b.lock().unwrap(), ["/start", "/next"]);
+    }
+
+    #[tokio::test]
+    async fn test_digest_multipart() {
+        let bodies = Arc::new(Mutex::new(Vec::new()));
+        let seen = bodies.clone();
+        let url = testing::serve(move |req| {
+            seen.lock()
+                .unwrap()
+                .push(String::from_utf8_lossy(&req.body).into_owned());
+            match req.header("authorization") {
+                Some(a) if a.starts_with("Digest ") => Response::new(200),
+                _ => Response::new(401)
+                    .header("WWW-Authenticate", r#"Digest realm="stub", nonce="n""#),
+            }
+        })
+        .await;
+        let file = testing::temp_path("up.txt");
+        fs::write(&file, "uploaded").unwrap();
+        let upload = format!("up@{}", file.display());
+        let opts = Opts::parse_from([
+            "minihttpie",
+            "post",
+            &url,
+            &upload,
+            "--multipart",
+            "-a",
+            "user:secret",
+            "--auth-type",
+            "digest",
+            "-q",
+            "--ignore-stdin",
+        ]);
+        let subcmd = opts.subcmd.unwrap();
+        let status = send(
+            Client::new(),
+            &Config::default(),
+            subcmd.method(),
+            subcmd.args(),
+        )
+        .await
+        .unwrap();
+        fs::remove_file(&file).unwrap();
+
+        assert_eq!(status, StatusCode::OK);
+        let bodies = bodies.lock().unwrap();
+        assert_eq!(bodies.len(), 2);
+        assert!(bodies[1].contains("uploaded"));
+    }
+
+    #[tokio::test]
+    async fn test_continue_complete() {
+        let url = testing::serve(|req| match req.header("range") {
+            Some("bytes=5-") => Response::new(416).header("Content-Range", "bytes */5"),
+            _ => Response::new(200).body("whole"),
+        })
+        .await;
+        let path = testing::temp_path("complete.txt");
+        fs::write(&path, "whole").unwrap();
+        let output = path.display().to_string();
+        let opts = Opts::parse_from([
+            "minihttpie",
+            "get",
+            &url,
+            "-o",
+            &output,
+            "--continue",
+            "--check-status",
+            "-q",
+            "--ignore-stdin",
+        ]);
+        let subcmd = opts.subcmd.unwrap();
+        let status = send(
+            Client::new(),
+            &Config::default(),
+            subcmd.method(),
+            subcmd.args(),
+        )
+        .await
+        .unwrap();
+
+        assert_eq!(status, StatusCode::OK);
+        assert_eq!(fs::read_to_string(&path).unwrap(), "whole");
+        fs::remove_file(&path).unwrap();
+    }
 
     #[test]
     fn test_parse_url() {
@@ -178,24 +565,11 @@ mod tests {
         assert!(parse_url("http://abc.xyz").is_ok());
         assert!(parse_url("https://goog.job").is_ok());
     }
-    #[test]
-    fn test_parse_kv_pair() {
-        assert!(parse_kv_pair("a").is_err());
-
-        assert_eq!(
-            parse_kv_pair("name=bob").unwrap(),
-            KvPair {
-                k: "name".into(),
-                v: "bob".into()
-            }
-        );
 
-        assert_eq!(
-            parse_kv_pair("age=").unwrap(),
-            KvPair {
-                k: "age".into(),
-                v: "".into()
-            }
-        );
+    #[test]
+    fn test_parse_method() {
+        assert_eq!(parse_method("purge").unwrap().as_str(), "PURGE");
+        assert_eq!(parse_method("GET").unwrap(), Method::GET);
+        assert!(parse_method("BAD METHOD").is_err());
     }
 }
@@ -0,0 +1,232 @@
+use anyhow::{anyhow, Result};
+use serde_json::{Map, Value};
+
+/// the largest array index a key may use, arrays are filled up to it with nulls.
+const MAX_INDEX: usize = 10_000;
+
+/// one step of a bracketed key like `user[tags][]` or `items[0][id]`.
+#[derive(Debug, PartialEq)]
+enum Step {
+    Key(String),
+    Index(usize),
+    Append,
+}
+
+/// split a key into its steps, `\[` and `\]` are literal brackets.
+fn parse_path(key: &str) -> Result<Vec<Step>> {
+    let err = |reason: &str| anyhow!("Invalid key {}: {}", key, reason);
+    let mut steps = Vec::new();
+    let mut current = String::new();
+    let mut in_brackets = false;
+    let mut chars = key.chars();
+    while let Some(c) = chars.next() {
+        match c {
+            '\\' => match chars.next() {
+                Some(escaped @ ('[' | ']' | '\\')) => current.push(escaped),
+                Some(other) => {
+                    current.push(c);
+                    current.push(other);
+                }
+                None => current.push(c),
+            },
+            '[' if !in_brackets => {
+                if steps.is_empty() {
+                    if current.is_empty() {
+                        return Err(err("missing name before `[`"));
+                    }
+                    steps.push(Step::Key(std::mem::take(&mut current)));
+                } else if !current.is_empty() {
+                    return Err(err("unexpected text after `]`"));
+                }
+                in_brackets = true;
+            }
+            ']' if in_brackets => {
+                let step =
+                    match current.as_str() {
+                        "" => Step::Append,
+                        s if s.bytes().all(|b| b.is_ascii_digit()) => {
+                            let index = s.parse().ok().filter(|&i| i <= MAX_INDEX);
+                            Step::Index(index.ok_or_else(|| {
+                                err(&format!("index {} is above {}", s, MAX_INDEX))
+                            })?)
+                        }
+                        _ => Step::Key(current.clone()),
+                    };
+                steps.push(step);
+                current.clear();
+                in_brackets = false;
+            }
+            '[' => return Err(err("nested `[`")),
+            _ => current.push(c),
+        }
+    }
+    if in_brackets {
+        return Err(err("missing `]`"));
+    }
+    if steps.is_empty() {
+        steps.push(Step::Key(current));
+    } else if !current.is_empty() {
+        return Err(err("unexpected text after `]`"));
+    }
+    Ok(steps)
+}
+
+fn type_name(value: &Value) -> &'static str {
+    match value {
+        Value::Null => "null",
+        Value::Bool(_) => "a boolean",
+        Value::Number(_) => "a number",
+        Value::String(_) => "a string",
+        Value::Array(_) => "an array",
+        Value::Object(_) => "an object",
+    }
+}
+
+fn display(steps: &[Step]) -> String {
+    steps
+        .iter()
+        .enumerate()
+        .map(|(i, step)| match step {
+            Step::Key(k) if i == 0 => k.clone(),
+            Step::Key(k) => format!("[{}]", k),
+            Step::Index(index) => format!("[{}]", index),
+            Step::Append => "[]".into(),
+        })
+        .collect()
+}
+
+/// set `value` at the bracketed `key` of `root`, creating objects and arrays on the way.
+pub fn insert(root: &mut Map<String, Value>, key: &str, value: Value) -> Result<()> {
+    let steps = parse_path(key)?;
+    let conflict = |found: &Value, expected: &str, depth: usize| {
+        anyhow!(
+            "Conflicting paths at {}: expected {} at {}, found {}",
+            key,
+            expected,
+            display(&steps[..depth]),
+            type_name(found)
+        )
+    };
+
+    let name = match &steps[0] {
+        Step::Key(name) => name,
+        _ => unreachable!("a path always starts with a name"),
+    };
+    let mut slot = root.entry(name.clone()).or_insert(Value::Null);
+    for (depth, step) in steps.iter().enumerate().skip(1) {
+        slot = match step {
+            Step::Key(k) => {
+                if slot.is_null() {
+                    *slot = Value::Object(Map::new());
+                }
+                match slot {
+                    Value::Object(map) => map.entry(k.clone()).or_insert(Value::Null),
+                    other => return Err(conflict(other, "an object", depth)),
+                }
+            }
+            Step::Index(index) => {
+                if slot.is_null() {
+                    *slot = Value::Array(Vec::new());
+                }
+                match slot {
+                    Value::Array(arr) => {
+                        if arr.len() <= *index {
+                            arr.resize(index + 1, Value::Null);
+                        }
+                        &mut arr[*index]
+                    }
+                    other => return Err(conflict(other, "an array", depth)),
+                }
+            }
+            Step::Append => {
+                if slot.is_null() {
+                    *slot = Value::Array(Vec::new());
+                }
+                match slot {
+                    Value::Array(arr) => {
+                        arr.push(Value::Null);
+                        arr.last_mut().unwrap()
+                    }
+                    other => return Err(conflict(other, "an array", depth)),
+                }
+            }
+        };
+    }
+
+    // only plain values can be overwritten, replacing a nested path is a conflict
+    if slot.is_object() || slot.is_array() {
+        return Err(conflict(slot, "a plain value", steps.len()));
+    }
+    *slot = value;
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    fn build(fields: &[(&str, Value)]) -> Result<Value> {
+        let mut root = Map::new();
+        for (k, v) in fields {
+            insert(&mut root, k, v.clone())?;
+        }
+        Ok(Value::Object(root))
+    }
+
+    #[test]
+    fn test_parse_path() {
+        assert_eq!(parse_path("name").unwrap(), vec![Step::Key("name".into())]);
+        assert_eq!(
+            parse_path("items[0][]").unwrap(),
+            vec![Step::Key("items".into()), Step::Index(0), Step::Append]
+        );
+        assert_eq!(
+            parse_path(r"a\[b\]").unwrap(),
+            vec![Step::Key("a[b]".into())]
+        );
+        assert!(parse_path("[0]").is_err());
+        assert!(parse_path("a[b").is_err());
+        assert!(parse_path("a[b]c").is_err());
+        assert!(parse_path("a[10000]").is_ok());
+        assert_eq!(
+            parse_path("a[10001]").unwrap_err().to_string(),
+            "Invalid key a[10001]: index 10001 is above 10000"
+        );
+    }
+
+    #[test]
+    fn test_insert_nested() {
+        let body = build(&[
+            ("user[name]", json!("bob")),
+            ("user[tags][]", json!("a")),
+            ("user[tags][]", json!("b")),
+            ("items[1][id]", json!(3)),
+            ("plain", json!("x")),
+        ])
+        .unwrap();
+        assert_eq!(
+            body,
+            json!({
+                "user": {"name": "bob", "tags": ["a", "b"]},
+                "items": [null, {"id": 3}],
+                "plain": "x"
+            })
+        );
+    }
+
+    #[test]
+    fn test_insert_conflicts() {
+        let err = build(&[("a", json!("1")), ("a[b]", json!("2"))]).unwrap_err();
+        assert_eq!(
+            err.to_string(),
+            "Conflicting paths at a[b]: expected an object at a, found a string"
+        );
+        assert!(build(&[("a[b]", json!(1)), ("a[0]", json!(2))]).is_err());
+        assert!(build(&[("a[b]", json!(1)), ("a", json!(2))]).is_err());
+        assert!(build(&[("a", json!(1)), ("a", json!(2))]).is_ok());
+        let err = build(&[("a[18446744073709551615]", json!(1))]).unwrap_err();
+        assert!(err.to_string().starts_with("Invalid key"));
+        assert!(build(&[("a[1000000000000]", json!(1))]).is_err());
+    }
+}
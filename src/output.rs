@@ -0,0 +1,584 @@
+use std::env;
+use std::io::{self, IsTerminal, Write};
+use std::str::FromStr;
+
+use anyhow::{anyhow, Result};
+use clap::{Parser, ValueEnum};
+use colored::*;
+use encoding_rs::{Decoder, Encoding, UTF_8};
+use mime::Mime;
+use reqwest::{
+    header::{self, HeaderMap},
+    Method, Request, Response,
+};
+use syntect::highlighting::Theme;
+use syntect::util::LinesWithEndings;
+
+use crate::format::{format_json, format_xml, FormatOptions};
+use crate::highlight::{self, highlighting_text, syntax_extension, Highlighter, DEFAULT_STYLE};
+
+/// which parts of the request and response are printed.
+#[derive(Debug, Clone, Copy, Default, PartialEq)]
+pub struct Print {
+    pub request_headers: bool,
+    pub request_body: bool,
+    pub response_headers: bool,
+    pub response_body: bool,
+}
+
+impl FromStr for Print {
+    type Err = anyhow::Error;
+
+    /// `H` request headers, `B` request body, `h` response headers, `b` response body.
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let mut print = Print::default();
+        for c in s.chars() {
+            match c {
+                'H' => print.request_headers = true,
+                'B' => print.request_body = true,
+                'h' => print.response_headers = true,
+                'b' => print.response_body = true,
+                _ => return Err(anyhow!("Invalid --print {}, expected any of HBhb", s)),
+            }
+        }
+        Ok(print)
+    }
+}
+
+/// how the output is prettified, `--pretty=none` prints bodies byte for byte.
+#[derive(ValueEnum, Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Pretty {
+    All,
+    Colors,
+    Format,
+    None,
+}
+
+impl Pretty {
+    pub fn colors(self) -> bool {
+        matches!(self, Pretty::All | Pretty::Colors)
+    }
+
+    pub fn format(self) -> bool {
+        matches!(self, Pretty::All | Pretty::Format)
+    }
+
+    fn raw(self) -> bool {
+        self == Pretty::None
+    }
+}
+
+/// output options shared by every method.
+#[derive(Parser, Debug)]
+pub struct OutputOpts {
+    /// what to print: H request headers, B request body, h response headers, b response body
+    #[clap(short, long, parse(try_from_str))]
+    print: Option<Print>,
+    /// print only the response headers, same as --print=h
+    #[clap(long)]
+    headers: bool,
+    /// print only the response body, same as --print=b
+    #[clap(short, long)]
+    body: bool,
+    /// print the request as well as the response, same as --print=HBhb
+    #[clap(short, long)]
+    verbose: bool,
+    /// print nothing
+    #[clap(short, long)]
+    quiet: bool,
+    /// colors and formatting, defaults to all on a terminal and none when piped
+    #[clap(long, value_enum)]
+    pretty: Option<Pretty>,
+    /// formatting of bodies, e.g. json.indent:2,json.sort_keys:true,json.format:false
+    #[clap(long, parse(try_from_str))]
+    format_options: Option<FormatOptions>,
+    /// sort the keys of JSON bodies, same as --format-options=json.sort_keys:true
+    #[clap(long)]
+    sort_keys: bool,
+    /// the highlighting theme, see --list-styles
+    #[clap(short, long)]
+    style: Option<String>,
+}
+
+impl OutputOpts {
+    /// the parts to print, HEAD responses never have a body.
+    pub fn print(&self, method: &Method) -> Print {
+        self.print_to(method, io::stdout().is_terminal())
+    }
+
+    /// the parts to print to a terminal, or only the response body when piped so that
+    /// the output can go on to e.g. jq.
+    fn print_to(&self, method: &Method, terminal: bool) -> Print {
+        let mut print = if self.quiet {
+            Print::default()
+        } else if let Some(print) = self.print {
+            print
+        } else if self.headers || self.body {
+            Print {
+                response_headers: self.headers,
+                response_body: self.body,
+                ..Print::default()
+            }
+        } else if self.verbose || terminal {
+            Print {
+                request_headers: self.verbose,
+                request_body: self.verbose,
+                response_headers: true,
+                response_body: true,
+            }
+        } else {
+            Print {
+                response_body: true,
+                ..Print::default()
+            }
+        };
+        if method == Method::HEAD {
+            print.response_body = false;
+        }
+        print
+    }
+
+    /// the --pretty mode, colors are dropped when `NO_COLOR` is set.
+    pub fn pretty(&self) -> Pretty {
+        let pretty = self
+            .pretty
+            .unwrap_or_else(|| match io::stdout().is_terminal() {
+                true => Pretty::All,
+                false => Pretty::None,
+            });
+        let no_color = env::var_os("NO_COLOR").is_some_and(|v| !v.is_empty());
+        match pretty {
+            Pretty::All if no_color => Pretty::Format,
+            Pretty::Colors if no_color => Pretty::None,
+            pretty => pretty,
+        }
+    }
+
+    pub fn format_options(&self) -> FormatOptions {
+        let mut options = self.format_options.unwrap_or_default();
+        options.json_sort_keys |= self.sort_keys;
+        options
+    }
+
+    pub fn printer(&self, method: &Method) -> Result<Printer> {
+        Ok(Printer {
+            print: self.print(method),
+            pretty: self.pretty(),
+            format: self.format_options(),
+            theme: highlight::theme(self.style.as_deref().unwrap_or(DEFAULT_STYLE))?,
+        })
+    }
+}
+
+/// prints requests and responses the way the output options ask for.
+#[derive(Debug, Clone, Copy)]
+pub struct Printer {
+    pub print: Print,
+    pub pretty: Pretty,
+    pub format: FormatOptions,
+    pub theme: &'static Theme,
+}
+
+fn print_status(resp: &Response) {
+    let status = format!("{:?} {}", resp.version(), resp.status()).blue();
+    println!("{}\n", status);
+}
+
+fn print_request_line(req: &Request) {
+    let url = req.url();
+    let path = match url.query() {
+        Some(query) => format!("{}?{}", url.path(), query),
+        None => url.path().to_string(),
+    };
+    let line = format!("{} {} {:?}", req.method(), path, req.version()).blue();
+    println!("{}\n", line);
+}
+
+/// values are shown even when marked sensitive, like credentials from --auth.
+fn print_headers(headers: &HeaderMap) {
+    for (name, value) in headers {
+        let value = String::from_utf8_lossy(value.as_bytes());
+        println!("{}: {:?}", name.to_string().green(), value);
+    }
+}
+
+/// guess the type of a text body without a usable Content-Type from how it starts.
+fn sniff(body: &str) -> Option<Mime> {
+    let start = body.trim_start();
+    let lower = start.get(..14).unwrap_or(start).to_ascii_lowercase();
+    if (start.starts_with('{') || start.starts_with('['))
+        && serde_json::from_str::<serde::de::IgnoredAny>(body).is_ok()
+    {
+        Some(mime::APPLICATION_JSON)
+    } else if lower.starts_with("<!doctype html") || lower.starts_with("<html") {
+        Some(mime::TEXT_HTML)
+    } else if lower.starts_with("<?xml") {
+        Some(mime::TEXT_XML)
+    } else {
+        None
+    }
+}
+
+/// write the body untouched, for `--pretty=none` and binary data.
+fn write_raw(body: &[u8]) -> Result<()> {
+    let mut stdout = io::stdout().lock();
+    stdout.write_all(body)?;
+    stdout.flush()?;
+    Ok(())
+}
+
+const BINARY_NOTE: &str = "\
++-----------------------------------------+
+| NOTE: binary data not shown in terminal |
++-----------------------------------------+";
+
+/// binary by mime type, or else by a NUL byte near the start like `file` does.
+fn is_binary(m: Option<&Mime>, body: &[u8]) -> bool {
+    if let Some(m) = m {
+        let binary = match (m.type_(), m.subtype().as_str()) {
+            (mime::IMAGE, "svg") => false,
+            (mime::IMAGE | mime::AUDIO | mime::VIDEO | mime::FONT, _) => true,
+            (mime::APPLICATION, sub) => matches!(
+                sub,
+                "octet-stream"
+                    | "pdf"
+                    | "zip"
+                    | "gzip"
+                    | "x-gzip"
+                    | "x-tar"
+                    | "x-7z-compressed"
+                    | "x-protobuf"
+                    | "protobuf"
+                    | "wasm"
+                    | "msgpack"
+                    | "x-msgpack"
+            ),
+            _ => false,
+        };
+        if binary {
+            return true;
+        }
+    }
+    body.iter().take(1024).any(|&b| b == 0)
+}
+
+/// the decoder for the charset parameter, UTF-8 when it is missing or unknown.
+fn encoding(m: Option<&Mime>) -> &'static Encoding {
+    m.and_then(|m| m.get_param(mime::CHARSET))
+        .and_then(|charset| Encoding::for_label(charset.as_str().as_bytes()))
+        .unwrap_or(UTF_8)
+}
+
+/// decode `chunk` onto the end of `text`, partial characters wait for the next chunk.
+fn decode_chunk(decoder: &mut Decoder, chunk: &[u8], last: bool, text: &mut String) {
+    let needed = decoder
+        .max_utf8_buffer_length(chunk.len())
+        .unwrap_or(chunk.len() * 3 + 16);
+    text.reserve(needed);
+    let _ = decoder.decode_to_string(chunk, text, last);
+}
+
+impl Printer {
+    /// whether the body can be printed as it arrives, formatting and sniffing need all of it.
+    fn streams(&self, m: Option<&Mime>) -> bool {
+        let m = match m {
+            Some(m) if !self.pretty.raw() && !is_binary(Some(m), b"") => m,
+            _ => return false,
+        };
+        match syntax_extension(m) {
+            Some("json") => !(self.pretty.format() && self.format.json_format),
+            Some("xml") => !(self.pretty.format() && self.format.xml_format),
+            _ => true,
+        }
+    }
+
+    /// decode and highlight the body a line at a time as the chunks come in.
+    async fn stream_body(&self, m: Mime, mut resp: Response) -> Result<()> {
+        let extension = syntax_extension(&m).filter(|_| self.pretty.colors());
+        let mut highlighter = Highlighter::new(extension, self.theme);
+        let mut decoder = encoding(Some(&m)).new_decoder();
+        let mut text = String::new();
+        let mut last = None;
+        let mut first = true;
+        loop {
+            let chunk = resp.chunk().await?;
+            if first {
+                first = false;
+                if let Some(chunk) = chunk.as_ref().filter(|c| is_binary(None, c)) {
+                    let mut body = chunk.to_vec();
+                    while let Some(chunk) = resp.chunk().await? {
+                        body.extend_from_slice(&chunk);
+                    }
+                    return self.print_bytes(Some(m), &body);
+                }
+            }
+            let done = chunk.is_none();
+            decode_chunk(
+                &mut decoder,
+                chunk.as_deref().unwrap_or_default(),
+                done,
+                &mut text,
+            );
+            // hold back the unfinished last line unless the body is complete
+            let end = match text.rfind('\n') {
+                _ if done => text.len(),
+                Some(end) => end + 1,
+                None => continue,
+            };
+            let rest = text.split_off(end);
+            let mut stdout = io::stdout().lock();
+            for line in LinesWithEndings::from(&text) {
+                write!(stdout, "{}", highlighter.highlight_line(line))?;
+            }
+            stdout.flush()?;
+            last = text.chars().last().or(last);
+            text = rest;
+            if done {
+                break;
+            }
+        }
+        if last.is_some_and(|c| c != '\n') {
+            println!();
+        }
+        Ok(())
+    }
+
+    fn print_body(&self, m: Option<Mime>, body: &str) {
+        let extension = m.as_ref().and_then(syntax_extension);
+        let formatted = match extension {
+            Some("json") if self.pretty.format() => format_json(body, &self.format),
+            Some("xml") if self.pretty.format() => format_xml(body, &self.format),
+            _ => None,
+        };
+        let body = formatted.as_deref().unwrap_or(body);
+        match extension {
+            Some(extension) if self.pretty.colors() => {
+                highlighting_text(body, extension, self.theme)
+            }
+            _ => print!("{}", body),
+        }
+        if !body.is_empty() && !body.ends_with('\n') {
+            println!();
+        }
+    }
+
+    /// print a body: binary data becomes a note on a terminal and is written as is when
+    /// piped, text is decoded with its charset, formatted and highlighted.
+    fn print_bytes(&self, m: Option<Mime>, body: &[u8]) -> Result<()> {
+        if is_binary(m.as_ref(), body) {
+            if io::stdout().is_terminal() {
+                println!("{}", BINARY_NOTE);
+                return Ok(());
+            }
+            return write_raw(body);
+        }
+        if self.pretty.raw() {
+            return write_raw(body);
+        }
+        let text = encoding(m.as_ref()).decode(body).0.into_owned();
+        let m = m.or_else(|| sniff(&text));
+        self.print_body(m, &text);
+        Ok(())
+    }
+
+    /// print the request as it is sent, streamed bodies like multipart forms are not shown.
+    pub fn print_request(&self, req: &Request) -> Result<()> {
+        if self.print.request_headers {
+            print_request_line(req);
+            if let Some(host) = req.url().host_str() {
+                let host = match req.url().port() {
+                    Some(port) => format!("{}:{}", host, port),
+                    None => host.to_string(),
+                };
+                println!("{}: {:?}", "host".green(), host);
+            }
+            print_headers(req.headers());
+            println!();
+        }
+        if self.print.request_body {
+            if let Some(body) = req.body() {
+                match body.as_bytes() {
+                    Some(bytes) => self.print_bytes(get_content_type(req.headers()), bytes)?,
+                    None => println!("{}", "NOTE: streamed body not shown".yellow()),
+                }
+                println!();
+            }
+        }
+        Ok(())
+    }
+
+    /// print the status line and headers when they are selected.
+    pub fn print_resp_headers(&self, resp: &Response) {
+        if self.print.response_headers {
+            print_status(resp);
+            print_headers(resp.headers());
+        }
+    }
+
+    pub async fn print_resp(&self, resp: Response) -> Result<()> {
+        self.print_resp_headers(&resp);
+        if !self.print.response_body {
+            return Ok(());
+        }
+
+        if self.print.response_headers {
+            println!();
+        }
+        let mime = get_content_type(resp.headers());
+        if self.streams(mime.as_ref()) {
+            return self.stream_body(mime.unwrap(), resp).await;
+        }
+        let body = resp.bytes().await?;
+        self.print_bytes(mime, &body)
+    }
+}
+
+/// the Content-Type, `None` when it is missing or malformed so that the body is sniffed.
+fn get_content_type(headers: &HeaderMap) -> Option<Mime> {
+    let value = headers.get(header::CONTENT_TYPE)?;
+    String::from_utf8_lossy(value.as_bytes())
+        .trim()
+        .parse()
+        .ok()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::testing;
+    use reqwest::header::HeaderValue;
+
+    fn opts(args: &[&str]) -> OutputOpts {
+        testing::parse(args)
+    }
+
+    #[test]
+    fn test_parse_print() {
+        let print: Print = "Hb".parse().unwrap();
+        assert!(print.request_headers && print.response_body);
+        assert!(!print.request_body && !print.response_headers);
+        assert!("Hx".parse::<Print>().is_err());
+    }
+
+    #[test]
+    fn test_output_opts() {
+        let default = opts(&[]).print_to(&Method::GET, true);
+        assert_eq!(default, "hb".parse().unwrap());
+        assert_eq!(
+            opts(&["-v"]).print_to(&Method::GET, true),
+            "HBhb".parse().unwrap()
+        );
+        assert_eq!(
+            opts(&["--headers"]).print_to(&Method::GET, true),
+            "h".parse().unwrap()
+        );
+        assert_eq!(
+            opts(&["-b"]).print_to(&Method::GET, true),
+            "b".parse().unwrap()
+        );
+        assert_eq!(
+            opts(&["-v", "-p", "B"]).print_to(&Method::GET, true),
+            "B".parse().unwrap()
+        );
+        assert_eq!(
+            opts(&["-q", "-v"]).print_to(&Method::GET, true),
+            Print::default()
+        );
+        assert_eq!(
+            opts(&["-v"]).print_to(&Method::HEAD, true),
+            "HBh".parse().unwrap()
+        );
+
+        // piped output is only the body unless something else is asked for
+        let piped = |args: &[&str]| opts(args).print_to(&Method::GET, false);
+        assert_eq!(piped(&[]), "b".parse().unwrap());
+        assert_eq!(piped(&["-v"]), "HBhb".parse().unwrap());
+        assert_eq!(piped(&["--headers"]), "h".parse().unwrap());
+        assert_eq!(piped(&["-p", "hb"]), "hb".parse().unwrap());
+    }
+
+    #[test]
+    fn test_is_binary() {
+        let png: Mime = "image/png".parse().unwrap();
+        let svg: Mime = "image/svg+xml".parse().unwrap();
+        let json = mime::APPLICATION_JSON;
+        assert!(is_binary(Some(&png), b"text"));
+        assert!(!is_binary(Some(&svg), b"<svg/>"));
+        assert!(!is_binary(Some(&json), b"{}"));
+        assert!(is_binary(Some(&json), b"{\0}"));
+        assert!(is_binary(None, b"\x1f\x8b\x08\0"));
+        assert!(!is_binary(None, "héllo".as_bytes()));
+    }
+
+    #[test]
+    fn test_get_content_type() {
+        let mut headers = HeaderMap::new();
+        assert_eq!(get_content_type(&headers), None);
+
+        headers.insert(
+            header::CONTENT_TYPE,
+            "application/json; charset=utf-8".parse().unwrap(),
+        );
+        let m = get_content_type(&headers).unwrap();
+        assert_eq!(m.essence_str(), "application/json");
+        assert_eq!(m.get_param(mime::CHARSET).unwrap(), "utf-8");
+
+        headers.insert(
+            header::CONTENT_TYPE,
+            HeaderValue::from_bytes(b"text/\xff\xfe").unwrap(),
+        );
+        assert_eq!(get_content_type(&headers), None);
+        headers.insert(header::CONTENT_TYPE, "not a mime".parse().unwrap());
+        assert_eq!(get_content_type(&headers), None);
+    }
+
+    #[test]
+    fn test_sniff() {
+        assert_eq!(sniff(" {\"a\": 1}"), Some(mime::APPLICATION_JSON));
+        assert_eq!(sniff("{not json"), None);
+        assert_eq!(sniff("<!DOCTYPE html><html>"), Some(mime::TEXT_HTML));
+        assert_eq!(sniff("<?xml version=\"1.0\"?><a/>"), Some(mime::TEXT_XML));
+        assert_eq!(sniff("plain"), None);
+    }
+
+    #[test]
+    fn test_pretty() {
+        assert_eq!(opts(&["--pretty", "colors"]).pretty, Some(Pretty::Colors));
+        assert!(Pretty::All.colors() && Pretty::Colors.colors());
+        assert!(!Pretty::Format.colors() && !Pretty::None.colors());
+        assert!(Pretty::None.raw() && !Pretty::Format.raw());
+        assert!(Pretty::Format.format() && !Pretty::Colors.format());
+    }
+
+    #[test]
+    fn test_streams() {
+        let printer = |args: &[&str]| opts(args).printer(&Method::GET).unwrap();
+        let all = printer(&["--pretty", "all"]);
+        let html: Mime = "text/html".parse().unwrap();
+        assert!(all.streams(Some(&html)));
+        assert!(!all.streams(Some(&mime::APPLICATION_JSON)));
+        assert!(!all.streams(Some(&mime::IMAGE_PNG)));
+        assert!(!all.streams(None));
+        assert!(printer(&["--pretty", "colors"]).streams(Some(&mime::APPLICATION_JSON)));
+        assert!(!printer(&["--pretty", "none"]).streams(Some(&html)));
+    }
+
+    #[test]
+    fn test_decode_chunk() {
+        let mut decoder = UTF_8.new_decoder();
+        let mut text = String::new();
+        let bytes = "中".as_bytes();
+        decode_chunk(&mut decoder, &bytes[..1], false, &mut text);
+        assert_eq!(text, "");
+        decode_chunk(&mut decoder, &bytes[1..], true, &mut text);
+        assert_eq!(text, "中");
+    }
+
+    #[test]
+    fn test_format_options() {
+        assert_eq!(opts(&[]).format_options(), FormatOptions::default());
+        let options = opts(&["--format-options", "json.indent:2", "--sort-keys"]).format_options();
+        assert_eq!(options.json_indent, 2);
+        assert!(options.json_sort_keys);
+    }
+}
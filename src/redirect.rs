@@ -0,0 +1,211 @@
+use anyhow::{anyhow, Result};
+use clap::Parser;
+use reqwest::{
+    header::{self, HeaderMap},
+    Method, Request, StatusCode, Url,
+};
+
+use crate::exit::TooManyRedirects;
+
+/// redirect options shared by every method.
+#[derive(Parser, Debug)]
+pub struct RedirectOpts {
+    /// follow 30x Location redirects
+    #[clap(short = 'F', long)]
+    follow: bool,
+    /// the most redirects to follow with --follow
+    #[clap(long, default_value_t = 30)]
+    max_redirects: usize,
+    /// print the intermediate requests and responses of --follow as well
+    #[clap(long)]
+    all: bool,
+}
+
+impl RedirectOpts {
+    pub fn follow(&self) -> bool {
+        self.follow
+    }
+
+    pub fn all(&self) -> bool {
+        self.all
+    }
+
+    /// fail once more than --max-redirects have been followed.
+    pub fn check(&self, redirects: usize) -> Result<()> {
+        if redirects > self.max_redirects {
+            return Err(TooManyRedirects(self.max_redirects).into());
+        }
+        Ok(())
+    }
+}
+
+/// what is kept of a sent request to follow its redirects with: all of it, or all but a
+/// streamed body, which can't be sent twice.
+pub struct Sent {
+    req: Request,
+    streamed: bool,
+}
+
+impl Sent {
+    pub fn new(req: &Request) -> Self {
+        match req.try_clone() {
+            Some(req) => Sent {
+                req,
+                streamed: false,
+            },
+            None => {
+                let mut copy = Request::new(req.method().clone(), req.url().clone());
+                *copy.headers_mut() = req.headers().clone();
+                Sent {
+                    req: copy,
+                    streamed: true,
+                }
+            }
+        }
+    }
+
+    /// the whole request again, `None` when its body was streamed.
+    pub fn try_clone(&self) -> Option<Request> {
+        match self.streamed {
+            true => None,
+            false => self.req.try_clone(),
+        }
+    }
+}
+
+/// the request to follow a response from `url` to `sent` with, `None` when it is no
+/// redirect. 301 and 302 turn POST into GET and 303 turns everything but HEAD into GET,
+/// dropping the body, while 307 and 308 resend the request as it is, which fails for a
+/// streamed body. credentials are dropped when the redirect leaves the host.
+pub fn next(
+    sent: &Sent,
+    url: &Url,
+    status: StatusCode,
+    headers: &HeaderMap,
+) -> Result<Option<Request>> {
+    let req = &sent.req;
+    let location = match headers.get(header::LOCATION) {
+        Some(location) if status.is_redirection() => location,
+        _ => return Ok(None),
+    };
+    let location = String::from_utf8_lossy(location.as_bytes());
+    let url = url
+        .join(&location)
+        .map_err(|e| anyhow!("Invalid redirect location {}: {}", location, e))?;
+
+    let method = req.method();
+    let to_get = match status {
+        StatusCode::MOVED_PERMANENTLY | StatusCode::FOUND => method == Method::POST,
+        StatusCode::SEE_OTHER => method != Method::HEAD,
+        StatusCode::TEMPORARY_REDIRECT | StatusCode::PERMANENT_REDIRECT => false,
+        // 300 and 304 have nothing to follow
+        _ => return Ok(None),
+    };
+    let mut next = if to_get {
+        let mut next = Request::new(Method::GET, url.clone());
+        *next.headers_mut() = req.headers().clone();
+        for name in [
+            header::CONTENT_TYPE,
+            header::CONTENT_LENGTH,
+            header::CONTENT_ENCODING,
+            header::TRANSFER_ENCODING,
+        ] {
+            next.headers_mut().remove(name);
+        }
+        next
+    } else {
+        let mut next = sent
+            .try_clone()
+            .ok_or_else(|| anyhow!("Can't resend a streamed body to {}", url))?;
+        *next.url_mut() = url.clone();
+        next
+    };
+    if !same_origin(req.url(), &url) {
+        for name in [
+            header::AUTHORIZATION,
+            header::COOKIE,
+            header::PROXY_AUTHORIZATION,
+        ] {
+            next.headers_mut().remove(name);
+        }
+    }
+    Ok(Some(next))
+}
+
+pub fn same_origin(a: &Url, b: &Url) -> bool {
+    a.scheme() == b.scheme()
+        && a.host_str() == b.host_str()
+        && a.port_or_known_default() == b.port_or_known_default()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn redirect(method: Method, status: u16, location: &str) -> Option<Request> {
+        let mut req = Request::new(method, "http://example.com/a/b".parse().unwrap());
+        req.headers_mut()
+            .insert(header::AUTHORIZATION, "Basic x".parse().unwrap());
+        req.headers_mut()
+            .insert(header::CONTENT_TYPE, "text/plain".parse().unwrap());
+        *req.body_mut() = Some("body".into());
+        let mut headers = HeaderMap::new();
+        headers.insert(header::LOCATION, location.parse().unwrap());
+        let status = StatusCode::from_u16(status).unwrap();
+        next(&Sent::new(&req), &req.url().clone(), status, &headers).unwrap()
+    }
+
+    #[test]
+    fn test_next() {
+        let next = redirect(Method::POST, 302, "c").unwrap();
+        assert_eq!(next.method(), Method::GET);
+        assert_eq!(next.url().as_str(), "http://example.com/a/c");
+        assert!(next.body().is_none() && next.headers().get(header::CONTENT_TYPE).is_none());
+        assert!(next.headers().get(header::AUTHORIZATION).is_some());
+
+        assert_eq!(
+            redirect(Method::PUT, 301, "/c").unwrap().method(),
+            Method::PUT
+        );
+        assert_eq!(
+            redirect(Method::PUT, 303, "/c").unwrap().method(),
+            Method::GET
+        );
+        assert_eq!(
+            redirect(Method::HEAD, 303, "/c").unwrap().method(),
+            Method::HEAD
+        );
+
+        let next = redirect(Method::POST, 307, "https://other.com/").unwrap();
+        assert_eq!(next.method(), Method::POST);
+        assert_eq!(next.body().unwrap().as_bytes(), Some(&b"body"[..]));
+        assert!(next.headers().get(header::AUTHORIZATION).is_none());
+
+        assert!(redirect(Method::GET, 304, "/c").is_none());
+        assert!(redirect(Method::GET, 200, "/c").is_none());
+    }
+
+    #[test]
+    fn test_next_multipart() {
+        let form = reqwest::multipart::Form::new().text("a", "b");
+        let req = reqwest::Client::new()
+            .post("http://example.com/up")
+            .multipart(form)
+            .build()
+            .unwrap();
+        let sent = Sent::new(&req);
+        assert!(sent.try_clone().is_none());
+        let mut headers = HeaderMap::new();
+        headers.insert(header::LOCATION, "/done".parse().unwrap());
+
+        let get = next(&sent, req.url(), StatusCode::SEE_OTHER, &headers)
+            .unwrap()
+            .unwrap();
+        assert_eq!(get.method(), Method::GET);
+        assert_eq!(get.url().as_str(), "http://example.com/done");
+        assert!(get.body().is_none() && get.headers().get(header::CONTENT_TYPE).is_none());
+
+        let err = next(&sent, req.url(), StatusCode::TEMPORARY_REDIRECT, &headers).unwrap_err();
+        assert!(err.to_string().starts_with("Can't resend a streamed body"));
+    }
+}
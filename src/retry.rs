@@ -0,0 +1,229 @@
+use std::time::{Duration, SystemTime};
+
+use anyhow::{anyhow, Result};
+use clap::Parser;
+use colored::Colorize;
+use reqwest::{
+    header::{self, HeaderMap},
+    Client, ClientBuilder, Method, Request, Response, StatusCode,
+};
+
+use crate::util;
+
+/// the first backoff, doubled for every retry.
+const BACKOFF: Duration = Duration::from_secs(1);
+/// backoffs don't grow beyond this.
+const MAX_BACKOFF: Duration = Duration::from_secs(60);
+/// responses asking to Retry-After longer than this aren't retried.
+const MAX_RETRY_AFTER: Duration = Duration::from_secs(120);
+
+/// timeout and retry options shared by every method.
+#[derive(Parser, Debug)]
+pub struct RetryOpts {
+    /// the most seconds a request may take, reading the response included
+    #[clap(long, parse(try_from_str = parse_secs))]
+    timeout: Option<Duration>,
+    /// the most seconds to wait for a connection
+    #[clap(long, parse(try_from_str = parse_secs))]
+    connect_timeout: Option<Duration>,
+    /// retry failed connections, timeouts and 408, 429, 500, 502, 503 and 504 responses
+    /// this many times with exponential backoff, or as long as Retry-After asks, giving up
+    /// when that is more than two minutes
+    #[clap(long, default_value_t = 0)]
+    retry: u32,
+    /// retry methods which aren't idempotent as well, e.g. POST and PATCH
+    #[clap(long)]
+    retry_all_methods: bool,
+}
+
+fn parse_secs(s: &str) -> Result<Duration> {
+    let secs: f64 = s.parse()?;
+    Duration::try_from_secs_f64(secs).map_err(|_| anyhow!("Invalid number of seconds {}", s))
+}
+
+impl RetryOpts {
+    /// set the timeouts of the client.
+    pub fn client(&self, mut builder: ClientBuilder) -> ClientBuilder {
+        if let Some(timeout) = self.timeout {
+            builder = builder.timeout(timeout);
+        }
+        if let Some(timeout) = self.connect_timeout {
+            builder = builder.connect_timeout(timeout);
+        }
+        builder
+    }
+
+    /// send `req` and retry it as --retry asks, each retry is logged when `log` is set.
+    /// requests with streamed bodies are sent once.
+    pub async fn execute(&self, client: &Client, mut req: Request, log: bool) -> Result<Response> {
+        let retries = if self.retry_all_methods || idempotent(req.method()) {
+            self.retry
+        } else {
+            0
+        };
+        let mut attempt = 0;
+        loop {
+            let next = if attempt < retries {
+                req.try_clone()
+            } else {
+                None
+            };
+            let result = client.execute(req).await;
+            let next = match next {
+                Some(next) => next,
+                None => return Ok(result?),
+            };
+            let (delay, reason) = match &result {
+                Ok(resp) if retryable(resp.status()) => {
+                    match status_delay(resp.status(), resp.headers(), attempt, SystemTime::now()) {
+                        Ok(delay) => (delay, resp.status().to_string()),
+                        Err(why) => {
+                            if log {
+                                eprintln!("{}", why.yellow());
+                            }
+                            return Ok(result?);
+                        }
+                    }
+                }
+                Err(e) if e.is_connect() || e.is_timeout() => (backoff(attempt), e.to_string()),
+                _ => return Ok(result?),
+            };
+            attempt += 1;
+            if log {
+                let msg = format!(
+                    "Retry {}/{} in {:.1}s after {}",
+                    attempt,
+                    retries,
+                    delay.as_secs_f64(),
+                    reason
+                );
+                eprintln!("{}", msg.yellow());
+            }
+            tokio::time::sleep(delay).await;
+            req = next;
+        }
+    }
+}
+
+/// methods which can be sent twice without doing more than once, RFC 9110 9.2.2.
+fn idempotent(method: &Method) -> bool {
+    matches!(
+        *method,
+        Method::GET | Method::HEAD | Method::OPTIONS | Method::TRACE | Method::PUT | Method::DELETE
+    )
+}
+
+fn retryable(status: StatusCode) -> bool {
+    matches!(status.as_u16(), 408 | 429 | 500 | 502 | 503 | 504)
+}
+
+/// the delay before retrying a `status` response, `Err` with the reason to give up when
+/// its Retry-After asks for longer than `MAX_RETRY_AFTER`.
+fn status_delay(
+    status: StatusCode,
+    headers: &HeaderMap,
+    attempt: u32,
+    now: SystemTime,
+) -> std::result::Result<Duration, String> {
+    match retry_after(headers, now) {
+        Some(delay) if delay > MAX_RETRY_AFTER => Err(format!(
+            "Not retrying after {}, Retry-After of {}s is longer than {}s",
+            status,
+            delay.as_secs(),
+            MAX_RETRY_AFTER.as_secs()
+        )),
+        Some(delay) => Ok(delay),
+        None => Ok(backoff(attempt)),
+    }
+}
+
+/// the delay of a Retry-After header, given in seconds or as an HTTP date.
+fn retry_after(headers: &HeaderMap, now: SystemTime) -> Option<Duration> {
+    let value = headers.get(header::RETRY_AFTER)?.to_str().ok()?.trim();
+    match value.parse::<u64>() {
+        Ok(secs) => Some(Duration::from_secs(secs)),
+        Err(_) => {
+            let date = httpdate::parse_http_date(value).ok()?;
+            Some(date.duration_since(now).unwrap_or_default())
+        }
+    }
+}
+
+/// doubling backoff with jitter, somewhere between half and all of it.
+fn backoff(attempt: u32) -> Duration {
+    let full = BACKOFF
+        .saturating_mul(2u32.saturating_pow(attempt))
+        .min(MAX_BACKOFF);
+    full / 2 + full.mul_f64(jitter() / 2.0)
+}
+
+/// a number in [0, 1).
+fn jitter() -> f64 {
+    (util::random() >> 11) as f64 / (1u64 << 53) as f64
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_backoff() {
+        for attempt in 0..10 {
+            let full = (BACKOFF * 2u32.pow(attempt)).min(MAX_BACKOFF);
+            let delay = backoff(attempt);
+            assert!(delay >= full / 2 && delay <= full, "{:?}", delay);
+        }
+        assert!(backoff(100) <= MAX_BACKOFF);
+    }
+
+    #[test]
+    fn test_retry_after() {
+        let now = httpdate::parse_http_date("Sun, 06 Nov 1994 08:49:37 GMT").unwrap();
+        let after = |value: &str| {
+            let mut headers = HeaderMap::new();
+            headers.insert(header::RETRY_AFTER, value.parse().unwrap());
+            retry_after(&headers, now)
+        };
+        assert_eq!(after("120"), Some(Duration::from_secs(120)));
+        assert_eq!(
+            after("Sun, 06 Nov 1994 08:50:07 GMT"),
+            Some(Duration::from_secs(30))
+        );
+        assert_eq!(after("Sun, 06 Nov 1994 08:00:00 GMT"), Some(Duration::ZERO));
+        assert_eq!(after("later"), None);
+        assert_eq!(retry_after(&HeaderMap::new(), now), None);
+    }
+
+    #[test]
+    fn test_status_delay() {
+        let now = SystemTime::now();
+        let status = StatusCode::SERVICE_UNAVAILABLE;
+        let mut headers = HeaderMap::new();
+        let delay = status_delay(status, &headers, 0, now).unwrap();
+        assert!(delay >= BACKOFF / 2 && delay <= BACKOFF);
+
+        headers.insert(header::RETRY_AFTER, "120".parse().unwrap());
+        assert_eq!(status_delay(status, &headers, 0, now), Ok(MAX_RETRY_AFTER));
+        headers.insert(header::RETRY_AFTER, "86400".parse().unwrap());
+        assert_eq!(
+            status_delay(status, &headers, 0, now),
+            Err(
+                "Not retrying after 503 Service Unavailable, Retry-After of 86400s is longer than 120s"
+                    .into()
+            )
+        );
+    }
+
+    #[test]
+    fn test_parse_secs() {
+        assert_eq!(parse_secs("2.5").unwrap(), Duration::from_millis(2500));
+        assert!(parse_secs("-1").is_err());
+        assert!(parse_secs("soon").is_err());
+    }
+
+    #[test]
+    fn test_idempotent() {
+        assert!(idempotent(&Method::PUT) && idempotent(&Method::GET));
+        assert!(!idempotent(&Method::POST) && !idempotent(&Method::PATCH));
+    }
+}
@@ -0,0 +1,198 @@
+use std::{collections::BTreeMap, path::PathBuf};
+
+use anyhow::{anyhow, Context, Result};
+use clap::Parser;
+use reqwest::{
+    header::{self, HeaderMap, HeaderName, HeaderValue},
+    Url,
+};
+use serde::{Deserialize, Serialize};
+
+use crate::auth::SavedAuth;
+use crate::config::config_dir;
+use crate::cookies::{self, Cookie};
+use crate::util::{self, read_optional, write_private};
+
+/// session options shared by every method.
+#[derive(Parser, Debug)]
+pub struct SessionOpts {
+    /// keep cookies, header items and auth in a named session of the host, or in the
+    /// session file when it is a path
+    #[clap(long, conflicts_with = "session-read-only")]
+    session: Option<String>,
+    /// use a session like --session without updating it
+    #[clap(long)]
+    session_read_only: Option<String>,
+}
+
+impl SessionOpts {
+    /// the session for a request to `url`, a missing one starts out empty.
+    pub fn load(&self, url: &Url) -> Result<Option<Session>> {
+        let (name, read_only) = match (&self.session, &self.session_read_only) {
+            (Some(name), _) => (name, false),
+            (None, Some(name)) => (name, true),
+            (None, None) => return Ok(None),
+        };
+        let path = session_path(name, url)?;
+        let mut session: Session = match read_optional(&path)? {
+            Some(text) => serde_json::from_str(&text)
+                .with_context(|| format!("Invalid session file {}", path.display()))?,
+            None => Session::default(),
+        };
+        session.path = path;
+        session.read_only = read_only;
+        Ok(Some(session))
+    }
+}
+
+/// `name` itself when it has a path separator, or else `sessions/<host>/<name>.json`
+/// in the config directory, with the port as `<host>_<port>`.
+fn session_path(name: &str, url: &Url) -> Result<PathBuf> {
+    if name.contains(['/', '\\']) {
+        return Ok(name.into());
+    }
+    let host = match (url.host_str(), url.port()) {
+        (Some(host), Some(port)) => format!("{}_{}", host, port),
+        (Some(host), None) => host.to_string(),
+        (None, _) => return Err(anyhow!("Sessions need a host in the url")),
+    };
+    let dir = config_dir().ok_or_else(|| anyhow!("No config directory to keep sessions in"))?;
+    Ok(dir
+        .join("sessions")
+        .join(host)
+        .join(format!("{}.json", name)))
+}
+
+/// what is kept between requests of a session.
+#[derive(Debug, Default, Serialize, Deserialize)]
+#[serde(default)]
+pub struct Session {
+    /// header items, sent with every request of the session.
+    pub headers: BTreeMap<String, String>,
+    pub cookies: Vec<Cookie>,
+    pub auth: Option<SavedAuth>,
+    #[serde(skip)]
+    path: PathBuf,
+    #[serde(skip)]
+    read_only: bool,
+}
+
+/// headers which only make sense for the request they came with.
+fn per_request(name: &HeaderName) -> bool {
+    let name = name.as_str();
+    name.starts_with("content-") || name.starts_with("if-")
+}
+
+impl Session {
+    /// add the session headers and cookies, header items are applied on top.
+    pub fn apply(&self, headers: &mut HeaderMap, url: &Url) -> Result<()> {
+        for (name, value) in &self.headers {
+            headers.insert(name.parse::<HeaderName>()?, value.parse()?);
+        }
+        if let Some(cookie) = cookies::header(&self.cookies, url, util::now()) {
+            headers.insert(header::COOKIE, cookie.parse()?);
+        }
+        Ok(())
+    }
+
+    /// keep the header items and auth of a request to `url`, `Cookie` items become
+    /// session cookies and removed headers leave the session.
+    pub fn remember(
+        &mut self,
+        url: &Url,
+        items: &[(HeaderName, Option<HeaderValue>)],
+        auth: Option<SavedAuth>,
+    ) {
+        let now = util::now();
+        for (name, value) in items {
+            let value = value
+                .as_ref()
+                .map(|v| String::from_utf8_lossy(v.as_bytes()).into_owned());
+            match value {
+                Some(value) if name == header::COOKIE => {
+                    for pair in value.split(';') {
+                        let cookie = Cookie::parse(&format!("{}; Path=/", pair.trim()), url, now);
+                        if let Some(cookie) = cookie {
+                            cookies::store(&mut self.cookies, cookie, now);
+                        }
+                    }
+                }
+                _ if per_request(name) => {}
+                Some(value) => {
+                    self.headers.insert(name.to_string(), value);
+                }
+                None => {
+                    self.headers.remove(name.as_str());
+                }
+            }
+        }
+        if auth.is_some() {
+            self.auth = auth;
+        }
+    }
+
+    /// keep the cookies set by a response from `url`, expired ones are dropped.
+    pub fn store_cookies(&mut self, url: &Url, headers: &HeaderMap) {
+        cookies::store_all(&mut self.cookies, url, headers);
+    }
+
+    /// write the session back unless it is read-only.
+    pub fn save(&self) -> Result<()> {
+        if self.read_only {
+            return Ok(());
+        }
+        write_private(&self.path, &serde_json::to_string_pretty(self)?)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::items::{parse_kv_pair, RequestItems};
+
+    #[test]
+    fn test_session_path() {
+        let url: Url = "http://example.com:8080/x".parse().unwrap();
+        assert_eq!(
+            session_path("./s.json", &url).unwrap(),
+            PathBuf::from("./s.json")
+        );
+        let path = session_path("login", &url).unwrap();
+        assert!(path.ends_with("sessions/example.com_8080/login.json"));
+    }
+
+    #[test]
+    fn test_remember_and_apply() {
+        let url: Url = "http://example.com/".parse().unwrap();
+        let items = [
+            (
+                HeaderName::from_static("x-api"),
+                Some("v1".parse().unwrap()),
+            ),
+            (header::COOKIE, Some("theme=dark; lang=en".parse().unwrap())),
+            (header::CONTENT_TYPE, Some("text/plain".parse().unwrap())),
+        ];
+        let mut session = Session::default();
+        session.headers.insert("x-old".into(), "1".into());
+        session.remember(&url, &items, None);
+        session.remember(&url, &[(HeaderName::from_static("x-old"), None)], None);
+        let mut set_cookies = HeaderMap::new();
+        set_cookies.append(header::SET_COOKIE, "sid=abc; Path=/".parse().unwrap());
+        set_cookies.append(header::SET_COOKIE, "lang=; Max-Age=0".parse().unwrap());
+        session.store_cookies(&url, &set_cookies);
+
+        let mut headers = HeaderMap::new();
+        session.apply(&mut headers, &url).unwrap();
+        assert_eq!(headers["x-api"], "v1");
+        assert!(headers.get("x-old").is_none() && headers.get("content-type").is_none());
+        let cookie = headers[header::COOKIE].to_str().unwrap();
+        assert!(cookie.contains("theme=dark") && cookie.contains("sid=abc"));
+        assert!(!cookie.contains("lang"));
+
+        // a Cookie item adds to the session cookies instead of replacing them
+        let item = RequestItems::from_pairs(&[parse_kv_pair("Cookie:x=1").unwrap()]).unwrap();
+        item.apply_headers(&mut headers).unwrap();
+        let cookie = headers[header::COOKIE].to_str().unwrap();
+        assert!(cookie.contains("sid=abc") && cookie.contains("x=1"));
+    }
+}
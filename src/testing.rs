@@ -0,0 +1,135 @@
+use std::path::PathBuf;
+use std::sync::Arc;
+
+use clap::Parser;
+use tokio::io::{AsyncBufReadExt, AsyncReadExt, AsyncWriteExt, BufReader};
+use tokio::net::{TcpListener, TcpStream};
+
+/// `P` parsed from `args`, without the program name.
+pub fn parse<P: Parser>(args: &[&str]) -> P {
+    P::parse_from(std::iter::once("test").chain(args.iter().copied()))
+}
+
+/// a path in the temp dir which is `name` and unique to the test run.
+pub fn temp_path(name: &str) -> PathBuf {
+    std::env::temp_dir().join(format!("minihttpie-{}-{}", std::process::id(), name))
+}
+
+/// a request as the stub server read it, header names lowercased.
+pub struct Request {
+    pub method: String,
+    pub path: String,
+    pub headers: Vec<(String, String)>,
+    pub body: Vec<u8>,
+}
+
+impl Request {
+    pub fn header(&self, name: &str) -> Option<&str> {
+        self.headers
+            .iter()
+            .find(|(k, _)| k == name)
+            .map(|(_, v)| v.as_str())
+    }
+}
+
+/// the answer of the stub server, sent with a Content-Length.
+pub struct Response {
+    status: u16,
+    headers: Vec<(String, String)>,
+    body: String,
+}
+
+impl Response {
+    pub fn new(status: u16) -> Self {
+        Self {
+            status,
+            headers: Vec::new(),
+            body: String::new(),
+        }
+    }
+
+    pub fn header(mut self, name: &str, value: impl Into<String>) -> Self {
+        self.headers.push((name.into(), value.into()));
+        self
+    }
+
+    pub fn body(mut self, body: impl Into<String>) -> Self {
+        self.body = body.into();
+        self
+    }
+}
+
+/// an HTTP/1.1 server answering every request with `handler`, returns its base url.
+pub async fn serve<F>(handler: F) -> String
+where
+    F: Fn(&Request) -> Response + Send + Sync + 'static,
+{
+    let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+    let url = format!("http://{}", listener.local_addr().unwrap());
+    let handler = Arc::new(handler);
+    tokio::spawn(async move {
+        while let Ok((stream, _)) = listener.accept().await {
+            let handler = handler.clone();
+            tokio::spawn(async move {
+                let mut stream = BufReader::new(stream);
+                while let Some(req) = read_request(&mut stream).await {
+                    let resp = handler(&req);
+                    let mut head = format!("HTTP/1.1 {} X\r\n", resp.status);
+                    for (name, value) in &resp.headers {
+                        head += &format!("{}: {}\r\n", name, value);
+                    }
+                    head += &format!("Content-Length: {}\r\n\r\n", resp.body.len());
+                    let out = stream.get_mut();
+                    out.write_all(head.as_bytes()).await.unwrap();
+                    out.write_all(resp.body.as_bytes()).await.unwrap();
+                }
+            });
+        }
+    });
+    url
+}
+
+/// the next request on a connection, `None` once it is closed.
+async fn read_request(stream: &mut BufReader<TcpStream>) -> Option<Request> {
+    let mut line = String::new();
+    if stream.read_line(&mut line).await.ok()? == 0 {
+        return None;
+    }
+    let mut parts = line.split_whitespace();
+    let method = parts.next()?.to_string();
+    let path = parts.next()?.to_string();
+    let mut headers = Vec::new();
+    loop {
+        line.clear();
+        stream.read_line(&mut line).await.ok()?;
+        match line.trim_end().split_once(':') {
+            Some((name, value)) => {
+                headers.push((name.to_ascii_lowercase(), value.trim().to_string()))
+            }
+            None => break,
+        }
+    }
+    let mut req = Request {
+        method,
+        path,
+        headers,
+        body: Vec::new(),
+    };
+    if let Some(len) = req.header("content-length") {
+        req.body = vec![0; len.parse().ok()?];
+        stream.read_exact(&mut req.body).await.ok()?;
+    } else if req.header("transfer-encoding") == Some("chunked") {
+        loop {
+            line.clear();
+            stream.read_line(&mut line).await.ok()?;
+            let len = usize::from_str_radix(line.trim(), 16).ok()?;
+            let mut chunk = vec![0; len + 2];
+            stream.read_exact(&mut chunk).await.ok()?;
+            if len == 0 {
+                break;
+            }
+            req.body.extend_from_slice(&chunk[..len]);
+        }
+    }
+    Some(req)
+}
@@ -0,0 +1,63 @@
+use std::collections::hash_map::RandomState;
+use std::fs::{self, OpenOptions};
+use std::hash::{BuildHasher, Hasher};
+use std::io::{ErrorKind, Write};
+use std::path::Path;
+use std::time::UNIX_EPOCH;
+
+use anyhow::{Context, Result};
+
+/// seconds since the epoch.
+pub fn now() -> u64 {
+    UNIX_EPOCH.elapsed().unwrap_or_default().as_secs()
+}
+
+/// an unpredictable number, good enough for nonces and jitter without pulling in a
+/// random number generator.
+pub fn random() -> u64 {
+    let mut hasher = RandomState::new().build_hasher();
+    hasher.write_u128(UNIX_EPOCH.elapsed().unwrap_or_default().as_nanos());
+    hasher.finish()
+}
+
+/// the text of a file, `None` when it doesn't exist yet.
+pub fn read_optional(path: &Path) -> Result<Option<String>> {
+    match fs::read_to_string(path) {
+        Ok(text) => Ok(Some(text)),
+        Err(e) if e.kind() == ErrorKind::NotFound => Ok(None),
+        Err(e) => Err(e).with_context(|| format!("Failed to read {}", path.display())),
+    }
+}
+
+/// write a file holding secrets like tokens or cookies so only the user can read it,
+/// missing directories are created.
+pub fn write_private(path: &Path, contents: &str) -> Result<()> {
+    if let Some(dir) = path.parent() {
+        fs::create_dir_all(dir)?;
+    }
+    let mut options = OpenOptions::new();
+    options.write(true).create(true).truncate(true);
+    #[cfg(unix)]
+    std::os::unix::fs::OpenOptionsExt::mode(&mut options, 0o600);
+    let mut file = options
+        .open(path)
+        .with_context(|| format!("Failed to write {}", path.display()))?;
+    file.write_all(contents.as_bytes())?;
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::testing;
+
+    #[test]
+    fn test_read_optional() {
+        let path = testing::temp_path("private.txt");
+        assert_eq!(read_optional(&path).unwrap(), None);
+        write_private(&path, "secret").unwrap();
+        assert_eq!(read_optional(&path).unwrap().as_deref(), Some("secret"));
+        fs::remove_file(&path).unwrap();
+        assert!(read_optional(Path::new("/")).is_err());
+    }
+}